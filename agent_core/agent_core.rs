@@ -1,7 +1,43 @@
 mod agent_loop;
+mod backend;
+mod cassette;
 mod compaction;
+mod hooks;
+mod mcp;
+mod retry;
 mod session;
+mod session_log;
+mod session_store;
+mod structured_output;
+mod subagent;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+mod tool_output;
+mod tool_schema;
+mod usage;
 
-pub use agent_loop::{AgentEvent, AgentLoopConfig, AgentTool, agent_loop, agent_loop_continue};
-pub use compaction::CompactionConfig;
-pub use session::{PersistFn, Session, SessionFile};
+pub use agent_loop::{
+    AgentEvent, AgentLoopConfig, AgentTool, ApprovalFn, ToolApproval, ToolApprovalRequest,
+    agent_loop, agent_loop_continue, agent_loop_structured,
+};
+pub use backend::{
+    ChatEventStream, LlmBackend, ScriptedBackend, ScriptedRequest, ScriptedResponse,
+};
+pub use cassette::{
+    ApprovalRecord, Cassette, CassettePlayer, CassetteRecorder, CassetteRun, LlmCall,
+    RecordedError, RecordingBackend, ReplayBackend, ToolCallRecord, ToolCallResult,
+};
+pub use compaction::{BpeTokenCounter, CompactionConfig, HeuristicTokenCounter, TokenCounter};
+pub use hooks::{AgentHook, ToolHookDecision};
+pub use mcp::{McpConnection, McpToolServer};
+pub use retry::{RetryPolicy, TransientToolError};
+pub use session::{PersistFn, Session, SessionFile, SessionSummary};
+pub use session_store::{
+    JsonSessionStore, SessionSearchHit, SessionStore, SqliteSessionStore, migrate_json_sessions,
+    open_session_store,
+};
+pub use structured_output::ResponseSchema;
+pub use subagent::SubAgentTool;
+pub use tool_output::{READ_TOOL_OUTPUT, ToolOutputConfig, ToolOutputOverflow, ToolOutputStore};
+pub use tool_schema::{TypedTool, check_tool_schema, schema_for};
+pub use usage::{TokenUsage, usage_cost};
@@ -18,11 +18,28 @@ pub enum AgentEvent {
     /// Emitted for each text chunk received during streaming.
     MessageDelta { text: String },
 
+    /// Emitted for each reasoning chunk of reasoning models, before the answer is streamed.
+    /// Requires `capture_reasoning_content` in the chat options for some providers.
+    ///
+    /// The reasoning text is only streamed, it is not kept on the assistant message: genai has
+    /// no message part for it and never sends it back. Providers that need the reasoning of
+    /// earlier turns get it through the thought signatures, which are kept.
+    ReasoningDelta { text: String },
+
+    /// Emitted while the model streams a tool call, before the call is complete.
+    /// `partial_args` holds the (usually incomplete) JSON arguments received so far.
+    ToolCallDelta {
+        call_id: String,
+        tool_name: String,
+        partial_args: String,
+    },
+
     /// Emitted when the assistant response stream completes.
     /// Contains the full assistant ChatMessage (with tool calls if any).
     MessageEnd { message: genai::chat::ChatMessage },
 
-    /// Emitted before a tool starts executing.
+    /// Emitted before a tool starts executing. Calls that never run, because they were
+    /// denied, blocked or skipped, still get one, right before their `ToolExecutionEnd`.
     ToolExecutionStart {
         call_id: String,
         tool_name: String,
@@ -43,6 +60,43 @@ pub enum AgentEvent {
         is_error: bool,
     },
 
+    /// Emitted before a mutating tool call is handed to the `approval` callback
+    /// of the [`AgentLoopConfig`]. The tool only runs once the callback approves it.
+    ToolApprovalRequested {
+        call_id: String,
+        tool_name: String,
+        arguments: serde_json::Value,
+    },
+
+    /// Emitted after each LLM response with the tokens it consumed, including the summary
+    /// requests of compaction and, through [`crate::SubAgentTool`], the requests of sub-agents.
+    /// Requires `capture_usage` in the chat options.
+    Usage {
+        /// The model that answered, which differs from the configured one after a fallback.
+        model: String,
+        usage: crate::usage::TokenUsage,
+    },
+
+    /// Emitted before the LLM request is retried after a transient failure.
+    /// Text already streamed by the failed attempt is streamed again by the retry.
+    LlmRetry {
+        model: String,
+        /// 1 for the first retry.
+        retry: usize,
+        delay: std::time::Duration,
+        error: String,
+    },
+
+    /// Emitted when a model keeps failing and the next of the `fallback_models` is tried.
+    ModelFallback {
+        from_model: String,
+        to_model: String,
+        error: String,
+    },
+
+    /// Emitted when a steering message sent while the loop runs is added to the conversation.
+    SteeringMessage { message: genai::chat::ChatMessage },
+
     /// Emitted when automatic compaction starts.
     CompactionStart {
         estimated_tokens: usize,
@@ -57,7 +111,7 @@ pub enum AgentEvent {
 
     /// Emitted when the agent is aborted (either during streaming or tool execution).
     Aborted {
-        /// Where the abort occurred: "streaming" or "tool_execution".
+        /// Where the abort occurred: "streaming", "tool_approval" or "tool_execution".
         phase: String,
         /// If aborted during tool execution, the tool call ID.
         tool_call_id: Option<String>,
@@ -84,6 +138,22 @@ pub trait AgentTool: Send + Sync {
         false
     }
 
+    /// How long a single execution of this tool may take before it is abandoned and reported
+    /// to the LLM as a timeout.
+    ///
+    /// Default: `None` (use `AgentLoopConfig::tool_timeout`).
+    fn timeout(&self) -> Option<std::time::Duration> {
+        None
+    }
+
+    /// Maximum length, in characters, of a result sent to the LLM. Longer results are cut
+    /// following `AgentLoopConfig::tool_output`.
+    ///
+    /// Default: `None` (use `AgentLoopConfig::tool_output.max_chars`).
+    fn max_output_chars(&self) -> Option<usize> {
+        None
+    }
+
     /// Execute the tool with the given arguments.
     ///
     /// - `call_id`: the unique ID from the LLM's ToolCall, used to correlate results.
@@ -93,6 +163,8 @@ pub trait AgentTool: Send + Sync {
     ///
     /// Returns the result as a String. This becomes the `ToolResponse.content` sent back to the LLM.
     /// Errors are caught by the loop and sent to the LLM as error text (the loop does not abort).
+    /// Errors wrapping a [`crate::TransientToolError`] are retried first, following
+    /// `AgentLoopConfig::tool_retry`.
     async fn execute(
         &self,
         call_id: &str,
@@ -102,6 +174,31 @@ pub trait AgentTool: Send + Sync {
     ) -> anyhow::Result<String>;
 }
 
+/// A mutating tool call waiting for approval.
+#[derive(Debug, Clone)]
+pub struct ToolApprovalRequest {
+    pub call_id: String,
+    pub tool_name: String,
+    pub arguments: serde_json::Value,
+}
+
+/// The answer to a [`ToolApprovalRequest`].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolApproval {
+    /// Run the tool with the arguments the LLM chose.
+    Approve,
+    /// Do not run the tool. The reason is sent back to the LLM as the tool error.
+    Deny { reason: String },
+    /// Run the tool with these arguments instead.
+    Edit { arguments: serde_json::Value },
+}
+
+/// Async callback deciding whether a mutating tool call may run.
+pub type ApprovalFn = Box<
+    dyn Fn(ToolApprovalRequest) -> futures::future::BoxFuture<'static, ToolApproval> + Send + Sync,
+>;
+
 pub struct AgentLoopConfig {
     /// The model identifier (e.g., "gpt-4o", "claude-sonnet-4-20250514").
     pub model: String,
@@ -109,7 +206,9 @@ pub struct AgentLoopConfig {
     /// System prompt sent with every LLM request.
     pub system_prompt: String,
 
-    /// Available tools. Each tool's `definition()` is sent to the LLM.
+    /// Available tools. Each tool's `definition()` is sent to the LLM. Calls are checked
+    /// against the schema of the definition, a tool whose schema does not compile cannot be
+    /// called, see [`crate::check_tool_schema`].
     pub tools: Vec<Box<dyn AgentTool>>,
 
     /// Maximum number of turns (LLM calls) before the loop stops.
@@ -124,6 +223,41 @@ pub struct AgentLoopConfig {
     /// When enabled, the agent will summarize old messages when the estimated
     /// token count exceeds the configured budget.
     pub compaction: Option<crate::compaction::CompactionConfig>,
+
+    /// Called before every mutating tool (see [`AgentTool::is_read_only`]) runs.
+    /// Set to `None` to run mutating tools without asking. Read-only tools never ask.
+    pub approval: Option<ApprovalFn>,
+
+    /// Default time limit for a single tool execution. Tools can override it through
+    /// [`AgentTool::timeout`]. Set to `None` to wait for tools indefinitely. Default: 2 minutes.
+    pub tool_timeout: Option<std::time::Duration>,
+
+    /// Backoff for tools failing with a [`crate::TransientToolError`].
+    pub tool_retry: crate::retry::RetryPolicy,
+
+    /// Budget of the tool results sent to the LLM, so one large result cannot overflow the
+    /// context. Default: 50 000 characters, truncated past that.
+    pub tool_output: crate::tool_output::ToolOutputConfig,
+
+    /// Backoff for LLM requests failing with a transient error (rate limit, 5xx, dropped stream).
+    pub llm_retry: crate::retry::RetryPolicy,
+
+    /// Models tried in order, with the same request, once `model` keeps failing.
+    pub fallback_models: Vec<String>,
+
+    /// Skip the mutating tool calls that have not run yet when a steering message arrives,
+    /// so the model can reconsider them. Read-only tools run regardless. Default: false.
+    pub skip_mutating_tools_on_steering: bool,
+
+    /// Middleware called around LLM requests, tool calls and compaction, in order. Shared, so
+    /// sub-agents can run the same hooks, see [`crate::SubAgentTool::with_hooks`].
+    pub hooks: Vec<std::sync::Arc<dyn crate::hooks::AgentHook>>,
+
+    /// Schema the final answer must match, see [`agent_loop_structured`]. Once the model
+    /// answers without calling tools, it is asked for the answer in the schema, and asked
+    /// again with the violations while the answer does not match. These extra turns count
+    /// towards `max_turns`.
+    pub response_schema: Option<crate::structured_output::ResponseSchema>,
 }
 
 impl Default for AgentLoopConfig {
@@ -136,8 +270,23 @@ impl Default for AgentLoopConfig {
             chat_options: genai::chat::ChatOptions::default()
                 .with_capture_content(true)
                 .with_capture_usage(true)
-                .with_capture_tool_calls(true),
+                .with_capture_tool_calls(true)
+                .with_capture_reasoning_content(true),
             compaction: Some(crate::compaction::CompactionConfig::default()),
+            approval: None,
+            tool_timeout: Some(std::time::Duration::from_secs(120)),
+            tool_output: crate::tool_output::ToolOutputConfig::default(),
+            tool_retry: crate::retry::RetryPolicy::default(),
+            llm_retry: crate::retry::RetryPolicy {
+                max_retries: 3,
+                initial_backoff: std::time::Duration::from_secs(1),
+                max_backoff: std::time::Duration::from_secs(30),
+                multiplier: 2.0,
+            },
+            fallback_models: Vec::new(),
+            skip_mutating_tools_on_steering: false,
+            hooks: Vec::new(),
+            response_schema: None,
         }
     }
 }
@@ -148,6 +297,36 @@ struct AgentLoopContext {
 
     /// Current turn number (0-indexed). Incremented each time the LLM is called.
     turn_index: usize,
+
+    /// Messages sent by the caller while the loop runs, added before the next LLM call.
+    steering: Option<tokio::sync::mpsc::UnboundedReceiver<genai::chat::ChatMessage>>,
+
+    /// Times the model was asked for its answer in the `response_schema`.
+    structured_requests: usize,
+
+    /// The final answer, once it matches the `response_schema`.
+    structured_output: Option<serde_json::Value>,
+}
+
+impl AgentLoopContext {
+    /// Whether steering messages are waiting to be added to the conversation.
+    fn has_pending_steering(&self) -> bool {
+        self.steering.as_ref().is_some_and(|rx| !rx.is_empty())
+    }
+}
+
+/// The tools the LLM can call: the configured ones, then the built-in `read_tool_output` when
+/// large results are spilled to a store.
+fn available_tools(config: &AgentLoopConfig) -> impl Iterator<Item = &dyn AgentTool> {
+    let store = match &config.tool_output.overflow {
+        crate::tool_output::ToolOutputOverflow::Spill(store) => Some(store as &dyn AgentTool),
+        crate::tool_output::ToolOutputOverflow::Truncate => None,
+    };
+    config.tools.iter().map(|t| t.as_ref()).chain(store)
+}
+
+fn find_tool<'a>(config: &'a AgentLoopConfig, name: &str) -> Option<&'a dyn AgentTool> {
+    available_tools(config).find(|t| t.name() == name)
 }
 
 fn check_cancelled(cancel: &tokio_util::sync::CancellationToken) -> anyhow::Result<()> {
@@ -156,16 +335,79 @@ fn check_cancelled(cancel: &tokio_util::sync::CancellationToken) -> anyhow::Resu
 }
 
 pub async fn agent_loop(
-    client: &genai::Client,
+    client: &dyn crate::backend::LlmBackend,
     config: &AgentLoopConfig,
     initial_messages: Vec<genai::chat::ChatMessage>,
     event_tx: tokio::sync::mpsc::UnboundedSender<AgentEvent>,
     cancel: tokio_util::sync::CancellationToken,
     on_persist: Option<crate::session::PersistFn>,
+    steering: Option<tokio::sync::mpsc::UnboundedReceiver<genai::chat::ChatMessage>>,
 ) -> anyhow::Result<Vec<genai::chat::ChatMessage>> {
+    let ctx = start_loop(
+        client,
+        config,
+        initial_messages,
+        event_tx,
+        cancel,
+        on_persist,
+        steering,
+    )
+    .await?;
+    Ok(ctx.messages)
+}
+
+/// Runs [`agent_loop`] with a config whose `response_schema` is set, and returns the final
+/// answer parsed and checked against the schema along with the messages.
+///
+/// Fails when the answer still does not match after `max_retries` corrections, or when the
+/// loop reaches `max_turns` before answering.
+pub async fn agent_loop_structured(
+    client: &dyn crate::backend::LlmBackend,
+    config: &AgentLoopConfig,
+    initial_messages: Vec<genai::chat::ChatMessage>,
+    event_tx: tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+    cancel: tokio_util::sync::CancellationToken,
+    on_persist: Option<crate::session::PersistFn>,
+    steering: Option<tokio::sync::mpsc::UnboundedReceiver<genai::chat::ChatMessage>>,
+) -> anyhow::Result<(Vec<genai::chat::ChatMessage>, serde_json::Value)> {
+    anyhow::ensure!(
+        config.response_schema.is_some(),
+        "A structured agent loop needs a response schema"
+    );
+    let ctx = start_loop(
+        client,
+        config,
+        initial_messages,
+        event_tx,
+        cancel,
+        on_persist,
+        steering,
+    )
+    .await?;
+    let output = ctx.structured_output.ok_or_else(|| {
+        anyhow::anyhow!(
+            "Agent loop reached max turns ({}) before a structured answer",
+            config.max_turns
+        )
+    })?;
+    Ok((ctx.messages, output))
+}
+
+async fn start_loop(
+    client: &dyn crate::backend::LlmBackend,
+    config: &AgentLoopConfig,
+    initial_messages: Vec<genai::chat::ChatMessage>,
+    event_tx: tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+    cancel: tokio_util::sync::CancellationToken,
+    on_persist: Option<crate::session::PersistFn>,
+    steering: Option<tokio::sync::mpsc::UnboundedReceiver<genai::chat::ChatMessage>>,
+) -> anyhow::Result<AgentLoopContext> {
     let mut ctx = AgentLoopContext {
         messages: initial_messages,
         turn_index: 0,
+        steering,
+        structured_requests: 0,
+        structured_output: None,
     };
 
     if let Some(persist) = on_persist.as_ref() {
@@ -184,17 +426,19 @@ pub async fn agent_loop(
     .await?;
     event_tx.send(AgentEvent::AgentEnd)?;
 
-    Ok(ctx.messages)
+    Ok(ctx)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn agent_loop_continue(
-    client: &genai::Client,
+    client: &dyn crate::backend::LlmBackend,
     config: &AgentLoopConfig,
     mut history: Vec<genai::chat::ChatMessage>,
     follow_up: Vec<genai::chat::ChatMessage>,
     event_tx: tokio::sync::mpsc::UnboundedSender<AgentEvent>,
     cancel: tokio_util::sync::CancellationToken,
     on_persist: Option<crate::session::PersistFn>,
+    steering: Option<tokio::sync::mpsc::UnboundedReceiver<genai::chat::ChatMessage>>,
 ) -> anyhow::Result<Vec<genai::chat::ChatMessage>> {
     anyhow::ensure!(
         !history.is_empty(),
@@ -210,6 +454,9 @@ pub async fn agent_loop_continue(
     let mut ctx = AgentLoopContext {
         messages: history,
         turn_index: 0,
+        steering,
+        structured_requests: 0,
+        structured_output: None,
     };
 
     event_tx.send(AgentEvent::AgentStart)?;
@@ -228,13 +475,27 @@ pub async fn agent_loop_continue(
 }
 
 async fn run_loop(
-    client: &genai::Client,
+    client: &dyn crate::backend::LlmBackend,
     config: &AgentLoopConfig,
     ctx: &mut AgentLoopContext,
     event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
     cancel: &tokio_util::sync::CancellationToken,
     on_persist: Option<&crate::session::PersistFn>,
 ) -> anyhow::Result<()> {
+    // Every request, compaction summaries included, goes through the `before_llm_call` hooks
+    let hooked_client = crate::hooks::HookedBackend {
+        inner: client,
+        hooks: &config.hooks,
+    };
+    let client: &dyn crate::backend::LlmBackend = &hooked_client;
+    // Requests constrain the answer to the schema once the model was asked for it
+    let structured_options = config.response_schema.as_ref().map(|schema| {
+        config
+            .chat_options
+            .clone()
+            .with_response_format(schema.response_format())
+    });
+
     loop {
         // Guard: max turns
         if ctx.turn_index >= config.max_turns {
@@ -244,32 +505,54 @@ async fn run_loop(
 
         check_cancelled(cancel)?;
 
+        // --- Steering messages ---
+        let mut steered = false;
+        while let Some(message) = ctx.steering.as_mut().and_then(|rx| rx.try_recv().ok()) {
+            event_tx.send(AgentEvent::SteeringMessage {
+                message: message.clone(),
+            })?;
+            ctx.messages.push(message);
+            steered = true;
+        }
+        if steered && let Some(persist) = on_persist {
+            persist(&ctx.messages)?;
+        }
+
         // --- Compaction check ---
-        if let Some(ref compaction_config) = config.compaction {
-            if crate::compaction::should_compact(&ctx.messages, compaction_config) {
-                let estimated_tokens = crate::compaction::estimate_total_tokens(&ctx.messages);
-                event_tx.send(AgentEvent::CompactionStart {
-                    estimated_tokens,
-                    message_count: ctx.messages.len(),
-                })?;
+        if let Some(ref compaction_config) = config.compaction
+            && crate::compaction::should_compact(&ctx.messages, compaction_config)
+        {
+            let estimated_tokens = crate::compaction::estimate_total_tokens(
+                &ctx.messages,
+                compaction_config.token_counter.as_ref(),
+            );
+            event_tx.send(AgentEvent::CompactionStart {
+                estimated_tokens,
+                message_count: ctx.messages.len(),
+            })?;
 
-                let original_count = ctx.messages.len();
-                ctx.messages = crate::compaction::compact(
-                    client,
-                    &config.model,
-                    &ctx.messages,
-                    compaction_config,
-                )
-                .await?;
-
-                event_tx.send(AgentEvent::CompactionEnd {
-                    original_count,
-                    compacted_count: ctx.messages.len(),
+            let original_count = ctx.messages.len();
+            let (mut compacted, usage) =
+                crate::compaction::compact(client, &config.model, &ctx.messages, compaction_config)
+                    .await?;
+            if let Some(usage) = usage {
+                event_tx.send(AgentEvent::Usage {
+                    model: config.model.clone(),
+                    usage,
                 })?;
+            }
+            for hook in &config.hooks {
+                hook.on_compaction(&ctx.messages, &mut compacted).await?;
+            }
+            ctx.messages = compacted;
 
-                if let Some(persist) = on_persist {
-                    persist(&ctx.messages)?;
-                }
+            event_tx.send(AgentEvent::CompactionEnd {
+                original_count,
+                compacted_count: ctx.messages.len(),
+            })?;
+
+            if let Some(persist) = on_persist {
+                persist(&ctx.messages)?;
             }
         }
 
@@ -279,8 +562,15 @@ async fn run_loop(
         })?;
 
         // --- Stream assistant response ---
-        let assistant_message =
-            stream_assistant_response(client, config, &ctx.messages, event_tx, cancel).await?;
+        let options = match &structured_options {
+            Some(options) if ctx.structured_requests > 0 => options,
+            _ => &config.chat_options,
+        };
+        let mut assistant_message =
+            stream_with_fallback(client, config, options, &ctx.messages, event_tx, cancel).await?;
+        for hook in &config.hooks {
+            hook.after_llm_call(&mut assistant_message).await?;
+        }
 
         ctx.messages.push(assistant_message.clone());
 
@@ -292,16 +582,46 @@ async fn run_loop(
         let tool_calls = assistant_message.content.tool_calls();
 
         if tool_calls.is_empty() {
-            // No tool calls — agent is done
             event_tx.send(AgentEvent::TurnEnd {
                 turn_index: ctx.turn_index,
             })?;
-            break;
+            // No tool calls — agent is done, unless the caller steered it meanwhile
+            if ctx.has_pending_steering() {
+                ctx.turn_index += 1;
+                continue;
+            }
+            let Some(schema) = &config.response_schema else {
+                break;
+            };
+
+            // --- Structured answer ---
+            let answer = assistant_message.content.joined_texts().unwrap_or_default();
+            let violations = match schema.check(&answer)? {
+                Ok(output) => {
+                    ctx.structured_output = Some(output);
+                    break;
+                }
+                Err(violations) => violations,
+            };
+            anyhow::ensure!(
+                ctx.structured_requests <= schema.max_retries,
+                "The final answer does not match the response schema '{}' after {} retries: {violations}",
+                schema.name,
+                schema.max_retries
+            );
+            // The first answer was not asked for in the schema, its violations are not news
+            let violations = (ctx.structured_requests > 0).then_some(violations.as_str());
+            ctx.messages.push(schema.request_message(violations));
+            ctx.structured_requests += 1;
+            if let Some(persist) = on_persist {
+                persist(&ctx.messages)?;
+            }
+            ctx.turn_index += 1;
+            continue;
         }
 
         // --- Execute tool calls ---
-        let tool_responses =
-            execute_tool_calls(&tool_calls, &config.tools, event_tx, cancel).await?;
+        let tool_responses = execute_tool_calls(&tool_calls, config, ctx, event_tx, cancel).await?;
 
         // --- Append tool responses as messages ---
         for response in tool_responses {
@@ -333,7 +653,8 @@ fn build_chat_request(
     }
 
     // Add tool definitions from all registered tools
-    let tool_defs: Vec<genai::chat::Tool> = config.tools.iter().map(|t| t.definition()).collect();
+    let tool_defs: Vec<genai::chat::Tool> =
+        available_tools(config).map(|t| t.definition()).collect();
     if !tool_defs.is_empty() {
         request = request.with_tools(tool_defs);
     }
@@ -341,9 +662,99 @@ fn build_chat_request(
     request
 }
 
+/// Streams the assistant response from `config.model`, then from each of the
+/// `config.fallback_models` in order while the previous model keeps failing.
+async fn stream_with_fallback(
+    client: &dyn crate::backend::LlmBackend,
+    config: &AgentLoopConfig,
+    options: &genai::chat::ChatOptions,
+    messages: &[genai::chat::ChatMessage],
+    event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+    cancel: &tokio_util::sync::CancellationToken,
+) -> anyhow::Result<genai::chat::ChatMessage> {
+    // Collected rather than chained, a borrowing iterator held across the awaits below keeps
+    // the loop future from being `Send` when it is nested in a tool (see `SubAgentTool`)
+    let models: Vec<&str> = std::iter::once(config.model.as_str())
+        .chain(config.fallback_models.iter().map(String::as_str))
+        .collect();
+    let mut models = models.into_iter().peekable();
+
+    while let Some(model) = models.next() {
+        let error =
+            match stream_with_retries(client, config, options, model, messages, event_tx, cancel)
+                .await
+            {
+                Ok(message) => return Ok(message),
+                Err(e) => e,
+            };
+
+        match models.peek() {
+            Some(next_model) if !cancel.is_cancelled() => {
+                log::warn!("Model '{model}' failed, falling back to '{next_model}': {error}");
+                event_tx.send(AgentEvent::ModelFallback {
+                    from_model: model.to_string(),
+                    to_model: next_model.to_string(),
+                    error: error.to_string(),
+                })?;
+            }
+            _ => return Err(error),
+        }
+    }
+
+    anyhow::bail!("No model configured for the agent loop")
+}
+
+/// Streams the assistant response from `model`, retrying transient failures
+/// (rate limits, 5xx responses, dropped streams) following `config.llm_retry`.
+async fn stream_with_retries(
+    client: &dyn crate::backend::LlmBackend,
+    config: &AgentLoopConfig,
+    options: &genai::chat::ChatOptions,
+    model: &str,
+    messages: &[genai::chat::ChatMessage],
+    event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+    cancel: &tokio_util::sync::CancellationToken,
+) -> anyhow::Result<genai::chat::ChatMessage> {
+    let mut retry = 0;
+    loop {
+        let error = match stream_assistant_response(
+            client, config, options, model, messages, event_tx, cancel,
+        )
+        .await
+        {
+            Ok(message) => return Ok(message),
+            Err(e) => e,
+        };
+
+        if cancel.is_cancelled()
+            || retry >= config.llm_retry.max_retries
+            || !crate::retry::is_transient_llm_error(&error)
+        {
+            return Err(error);
+        }
+
+        let delay = config.llm_retry.backoff(retry);
+        retry += 1;
+        log::warn!("Model '{model}' failed, retry {retry} in {delay:?}: {error}");
+        event_tx.send(AgentEvent::LlmRetry {
+            model: model.to_string(),
+            retry,
+            delay,
+            error: error.to_string(),
+        })?;
+
+        tokio::select! {
+            () = tokio::time::sleep(delay) => {}
+            () = cancel.cancelled() => anyhow::bail!("Agent loop cancelled while waiting to retry"),
+        }
+    }
+}
+
 async fn stream_assistant_response(
-    client: &genai::Client,
+    client: &dyn crate::backend::LlmBackend,
     config: &AgentLoopConfig,
+    options: &genai::chat::ChatOptions,
+    model: &str,
     messages: &[genai::chat::ChatMessage],
     event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
     cancel: &tokio_util::sync::CancellationToken,
@@ -351,12 +762,12 @@ async fn stream_assistant_response(
     use futures::StreamExt;
 
     let chat_req = build_chat_request(config, messages);
-    let response = client
-        .exec_chat_stream(&config.model, chat_req, Some(&config.chat_options))
+    let mut stream = client
+        .exec_chat_stream(model, chat_req, Some(options))
         .await?;
-
-    let mut stream = response.stream;
     let mut stream_end: Option<genai::chat::StreamEnd> = None;
+    let mut thought_signatures: Vec<String> = Vec::new();
+    let mut tool_call_deltas = ToolCallDeltas::new(options.capture_tool_calls.unwrap_or(false));
 
     event_tx.send(AgentEvent::MessageStart)?;
 
@@ -387,25 +798,34 @@ async fn stream_assistant_response(
                     text: chunk.content.clone(),
                 })?;
             }
-            genai::chat::ChatStreamEvent::ReasoningChunk(_) => {}
-            genai::chat::ChatStreamEvent::ThoughtSignatureChunk(_) => {}
-            genai::chat::ChatStreamEvent::ToolCallChunk(_) => {}
+            genai::chat::ChatStreamEvent::ReasoningChunk(chunk) => {
+                event_tx.send(AgentEvent::ReasoningDelta {
+                    text: chunk.content,
+                })?;
+            }
+            genai::chat::ChatStreamEvent::ThoughtSignatureChunk(chunk) => {
+                thought_signatures.push(chunk.content);
+            }
+            genai::chat::ChatStreamEvent::ToolCallChunk(chunk) => {
+                event_tx.send(tool_call_deltas.push(chunk.tool_call))?;
+            }
             genai::chat::ChatStreamEvent::End(end) => {
                 stream_end = Some(end);
             }
         }
     }
 
-    let end = stream_end.ok_or_else(|| anyhow::anyhow!("Stream ended without End event"))?;
+    let end = stream_end.ok_or(crate::retry::IncompleteStream)?;
+
+    if let Some(usage) = end.captured_usage.as_ref() {
+        event_tx.send(AgentEvent::Usage {
+            model: model.to_string(),
+            usage: crate::usage::TokenUsage::from(usage),
+        })?;
+    }
 
-    let fallback_content = end
-        .captured_content
-        .as_ref()
-        .cloned()
-        .unwrap_or_else(|| genai::chat::MessageContent::from_text(""));
-    let assistant_msg = end
-        .into_assistant_message_for_tool_use()
-        .unwrap_or_else(|| genai::chat::ChatMessage::assistant(fallback_content));
+    // `end.captured_reasoning_content` has no place on a genai message, see `ReasoningDelta`
+    let assistant_msg = assistant_message(end.captured_content, thought_signatures);
 
     event_tx.send(AgentEvent::MessageEnd {
         message: assistant_msg.clone(),
@@ -414,40 +834,157 @@ async fn stream_assistant_response(
     Ok(assistant_msg)
 }
 
+/// Arguments received so far for each tool call being streamed.
+///
+/// Providers streaming the arguments as text send them in fragments, which genai accumulates
+/// when `capture_tool_calls` is set. The others send the complete arguments as JSON.
+struct ToolCallDeltas {
+    /// Whether text chunks hold the arguments accumulated so far rather than a fragment.
+    accumulated: bool,
+    calls: Vec<(String, String, String)>,
+}
+
+impl ToolCallDeltas {
+    fn new(accumulated: bool) -> Self {
+        Self {
+            accumulated,
+            calls: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, tool_call: genai::chat::ToolCall) -> AgentEvent {
+        let (chunk, is_fragment) = match tool_call.fn_arguments {
+            serde_json::Value::String(text) => (text, !self.accumulated),
+            serde_json::Value::Null => (String::new(), true),
+            arguments => (arguments.to_string(), false),
+        };
+
+        let index = match self
+            .calls
+            .iter()
+            .position(|(call_id, _, _)| *call_id == tool_call.call_id)
+        {
+            Some(index) => index,
+            None => {
+                self.calls
+                    .push((tool_call.call_id.clone(), String::new(), String::new()));
+                self.calls.len() - 1
+            }
+        };
+        let (call_id, tool_name, partial_args) = &mut self.calls[index];
+
+        if !tool_call.fn_name.is_empty() {
+            *tool_name = tool_call.fn_name;
+        }
+        if is_fragment {
+            partial_args.push_str(&chunk);
+        } else {
+            *partial_args = chunk;
+        }
+
+        AgentEvent::ToolCallDelta {
+            call_id: call_id.clone(),
+            tool_name: tool_name.clone(),
+            partial_args: partial_args.clone(),
+        }
+    }
+}
+
+/// Builds the assistant message from the content captured by the stream.
+///
+/// Text, thought signatures and tool calls are all kept. Thought signatures that were streamed
+/// but are missing from the captured content are put back in front of the other parts and on
+/// the first tool call, where providers look for them on the next request.
+fn assistant_message(
+    captured_content: Option<genai::chat::MessageContent>,
+    streamed_signatures: Vec<String>,
+) -> genai::chat::ChatMessage {
+    use genai::chat::ContentPart;
+
+    let mut parts = captured_content
+        .map(|content| content.into_parts())
+        .unwrap_or_default();
+
+    let has_signatures = parts
+        .iter()
+        .any(|part| matches!(part, ContentPart::ThoughtSignature(_)));
+    if !has_signatures && !streamed_signatures.is_empty() {
+        if let Some(call) = parts.iter_mut().find_map(|part| match part {
+            ContentPart::ToolCall(call) => Some(call),
+            _ => None,
+        }) {
+            call.thought_signatures
+                .get_or_insert_with(|| streamed_signatures.clone());
+        }
+        parts.splice(
+            0..0,
+            streamed_signatures
+                .into_iter()
+                .map(ContentPart::ThoughtSignature),
+        );
+    }
+
+    if parts.is_empty() {
+        parts.push(ContentPart::Text(String::new()));
+    }
+    genai::chat::ChatMessage::assistant(genai::chat::MessageContent::from_parts(parts))
+}
+
 async fn execute_tool_calls(
     tool_calls: &[&genai::chat::ToolCall],
-    tools: &[Box<dyn AgentTool>],
+    config: &AgentLoopConfig,
+    ctx: &AgentLoopContext,
     event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
     cancel: &tokio_util::sync::CancellationToken,
 ) -> anyhow::Result<Vec<genai::chat::ToolResponse>> {
     let mut results: Vec<Option<genai::chat::ToolResponse>> = vec![None; tool_calls.len()];
     let mut read_only_batch: Vec<(usize, &genai::chat::ToolCall)> = Vec::new();
-
     for (idx, tc) in tool_calls.iter().enumerate() {
-        let is_read_only = tools
-            .iter()
-            .find(|t| t.name() == tc.fn_name)
-            .is_some_and(|t| t.is_read_only());
+        let is_read_only = find_tool(config, &tc.fn_name).is_some_and(|t| t.is_read_only());
 
         if is_read_only {
             read_only_batch.push((idx, tc));
         } else {
             // Safety barrier: flush read-only batch before running mutating tool
             if !read_only_batch.is_empty() {
-                flush_read_only_batch(&read_only_batch, tools, event_tx, cancel, &mut results)
+                flush_read_only_batch(&read_only_batch, config, event_tx, cancel, &mut results)
                     .await?;
                 read_only_batch.clear();
             }
 
             check_cancelled(cancel)?;
 
-            results[idx] = Some(execute_single_tool(tc, tools, event_tx, cancel).await?);
+            if config.skip_mutating_tools_on_steering && ctx.has_pending_steering() {
+                results[idx] = Some(skip_tool_call(tc, event_tx)?);
+                continue;
+            }
+
+            let hooked_call = match run_before_tool_hooks(tc, config, event_tx).await? {
+                Ok(hooked_call) => hooked_call,
+                Err(blocked) => {
+                    results[idx] = Some(blocked);
+                    continue;
+                }
+            };
+
+            // Approval is asked for the call as the hooks left it, that is what runs
+            let approved_call =
+                match request_approval(&hooked_call, config, event_tx, cancel).await? {
+                    Ok(approved_call) => approved_call,
+                    Err(denied) => {
+                        results[idx] = Some(denied);
+                        continue;
+                    }
+                };
+
+            results[idx] =
+                Some(execute_single_tool(&approved_call, config, event_tx, cancel).await?);
         }
     }
 
     // Flush any remaining read-only tools
     if !read_only_batch.is_empty() {
-        flush_read_only_batch(&read_only_batch, tools, event_tx, cancel, &mut results).await?;
+        flush_read_only_batch(&read_only_batch, config, event_tx, cancel, &mut results).await?;
     }
 
     Ok(results
@@ -457,16 +994,130 @@ async fn execute_tool_calls(
         .collect())
 }
 
+/// Error response for a mutating tool call left out because the caller steered the loop.
+fn skip_tool_call(
+    tool_call: &genai::chat::ToolCall,
+    event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+) -> anyhow::Result<genai::chat::ToolResponse> {
+    let content = String::from(
+        "Tool call skipped: the user sent a new message before it ran. Read it and call the \
+         tool again if it is still needed.",
+    );
+    reject_tool_call(tool_call, content, event_tx)
+}
+
+/// Error response for a tool call that is not run, with the start and end events of the call.
+fn reject_tool_call(
+    tool_call: &genai::chat::ToolCall,
+    content: String,
+    event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+) -> anyhow::Result<genai::chat::ToolResponse> {
+    event_tx.send(AgentEvent::ToolExecutionStart {
+        call_id: tool_call.call_id.clone(),
+        tool_name: tool_call.fn_name.clone(),
+        arguments: tool_call.fn_arguments.clone(),
+    })?;
+    event_tx.send(AgentEvent::ToolExecutionEnd {
+        call_id: tool_call.call_id.clone(),
+        tool_name: tool_call.fn_name.clone(),
+        result: content.clone(),
+        is_error: true,
+    })?;
+    Ok(genai::chat::ToolResponse::new(
+        tool_call.call_id.clone(),
+        content,
+    ))
+}
+
+/// Asks the `approval` callback of the config whether a mutating tool call may run.
+///
+/// Returns the call to execute (with edited arguments if the callback changed them), or the
+/// error response to send back to the LLM when the call was denied. Unknown tools skip
+/// approval, they fail on their own in `execute_single_tool`.
+async fn request_approval(
+    tool_call: &genai::chat::ToolCall,
+    config: &AgentLoopConfig,
+    event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+    cancel: &tokio_util::sync::CancellationToken,
+) -> anyhow::Result<Result<genai::chat::ToolCall, genai::chat::ToolResponse>> {
+    let Some(approval) = config.approval.as_ref() else {
+        return Ok(Ok(tool_call.clone()));
+    };
+    if find_tool(config, &tool_call.fn_name).is_none() {
+        return Ok(Ok(tool_call.clone()));
+    }
+
+    event_tx.send(AgentEvent::ToolApprovalRequested {
+        call_id: tool_call.call_id.clone(),
+        tool_name: tool_call.fn_name.clone(),
+        arguments: tool_call.fn_arguments.clone(),
+    })?;
+
+    let decision = tokio::select! {
+        decision = approval(ToolApprovalRequest {
+            call_id: tool_call.call_id.clone(),
+            tool_name: tool_call.fn_name.clone(),
+            arguments: tool_call.fn_arguments.clone(),
+        }) => decision,
+        () = cancel.cancelled() => {
+            event_tx.send(AgentEvent::Aborted {
+                phase: "tool_approval".to_string(),
+                tool_call_id: Some(tool_call.call_id.clone()),
+            })?;
+            anyhow::bail!("Agent loop cancelled while waiting for tool approval");
+        }
+    };
+
+    match decision {
+        ToolApproval::Approve => Ok(Ok(tool_call.clone())),
+        ToolApproval::Edit { arguments } => {
+            let mut edited = tool_call.clone();
+            edited.fn_arguments = arguments;
+            Ok(Ok(edited))
+        }
+        ToolApproval::Deny { reason } => {
+            let content = format!("Tool call denied by the user: {reason}");
+            Ok(Err(reject_tool_call(tool_call, content, event_tx)?))
+        }
+    }
+}
+
+/// Runs the `before_tool` hooks of the config on a call.
+///
+/// Returns the call as the hooks rewrote it, or the error response to send back to the LLM
+/// when a hook blocked it.
+async fn run_before_tool_hooks(
+    tool_call: &genai::chat::ToolCall,
+    config: &AgentLoopConfig,
+    event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+) -> anyhow::Result<Result<genai::chat::ToolCall, genai::chat::ToolResponse>> {
+    let mut tool_call = tool_call.clone();
+    let crate::hooks::ToolHookDecision::Block { reason } =
+        crate::hooks::before_tool(&config.hooks, &mut tool_call).await?
+    else {
+        return Ok(Ok(tool_call));
+    };
+    let content = format!("Tool call blocked: {reason}");
+    Ok(Err(reject_tool_call(&tool_call, content, event_tx)?))
+}
+
 async fn flush_read_only_batch(
     batch: &[(usize, &genai::chat::ToolCall)],
-    tools: &[Box<dyn AgentTool>],
+    config: &AgentLoopConfig,
     event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
     cancel: &tokio_util::sync::CancellationToken,
     results: &mut [Option<genai::chat::ToolResponse>],
 ) -> anyhow::Result<()> {
     let futures: Vec<_> = batch
         .iter()
-        .map(|(_, tc)| execute_single_tool(tc, tools, event_tx, cancel))
+        .map(|(_, tc)| async move {
+            match run_before_tool_hooks(tc, config, event_tx).await? {
+                Ok(hooked_call) => {
+                    execute_single_tool(&hooked_call, config, event_tx, cancel).await
+                }
+                Err(blocked) => Ok(blocked),
+            }
+        })
         .collect();
 
     let batch_results = futures::future::join_all(futures).await;
@@ -478,9 +1129,10 @@ async fn flush_read_only_batch(
     Ok(())
 }
 
+/// Runs a call that went through the `before_tool` hooks, and approval when it mutates.
 async fn execute_single_tool(
     tool_call: &genai::chat::ToolCall,
-    tools: &[Box<dyn AgentTool>],
+    config: &AgentLoopConfig,
     event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
     cancel: &tokio_util::sync::CancellationToken,
 ) -> anyhow::Result<genai::chat::ToolResponse> {
@@ -491,34 +1143,10 @@ async fn execute_single_tool(
         arguments: tool_call.fn_arguments.clone(),
     })?;
 
-    // Find the tool by name
-    let tool = tools.iter().find(|t| t.name() == tool_call.fn_name);
-
-    let (content, is_error) = match tool {
-        Some(tool) => {
-            tokio::select! {
-                result = tool.execute(
-                    &tool_call.call_id,
-                    tool_call.fn_arguments.clone(),
-                    event_tx,
-                    cancel,
-                ) => {
-                    match result {
-                        Ok(result) => (result, false),
-                        Err(e) => (format!("Error executing tool: {e}"), true),
-                    }
-                }
-                () = cancel.cancelled() => {
-                    event_tx.send(AgentEvent::Aborted {
-                        phase: "tool_execution".to_string(),
-                        tool_call_id: Some(tool_call.call_id.clone()),
-                    })?;
-                    ("Tool execution aborted".to_string(), true)
-                }
-            }
-        }
-        None => (format!("Tool '{}' not found", tool_call.fn_name), true),
-    };
+    let (mut content, is_error) = run_tool(tool_call, config, event_tx, cancel).await?;
+    for hook in &config.hooks {
+        hook.after_tool(tool_call, &mut content, is_error).await?;
+    }
 
     // Emit end event
     event_tx.send(AgentEvent::ToolExecutionEnd {
@@ -533,3 +1161,709 @@ async fn execute_single_tool(
         content,
     ))
 }
+
+/// Finds the tool of the call, checks its arguments and runs it.
+/// Returns the content sent back to the LLM and whether it is an error.
+async fn run_tool(
+    tool_call: &genai::chat::ToolCall,
+    config: &AgentLoopConfig,
+    event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+    cancel: &tokio_util::sync::CancellationToken,
+) -> anyhow::Result<(String, bool)> {
+    // Find the tool by name
+    let Some(tool) = find_tool(config, &tool_call.fn_name) else {
+        return Ok((format!("Tool '{}' not found", tool_call.fn_name), true));
+    };
+
+    if let Err(violations) =
+        crate::tool_schema::validate_arguments(&tool.definition(), &tool_call.fn_arguments)
+    {
+        return Ok((violations, true));
+    }
+
+    let (content, is_error) = tokio::select! {
+        result = execute_with_retries(tool, tool_call, config, event_tx, cancel) => result,
+        () = cancel.cancelled() => {
+            event_tx.send(AgentEvent::Aborted {
+                phase: "tool_execution".to_string(),
+                tool_call_id: Some(tool_call.call_id.clone()),
+            })?;
+            ("Tool execution aborted".to_string(), true)
+        }
+    };
+    let content = crate::tool_output::limit_output(
+        content,
+        tool.max_output_chars().or(config.tool_output.max_chars),
+        &tool_call.call_id,
+        &config.tool_output.overflow,
+    );
+    Ok((content, is_error))
+}
+
+/// Runs `tool` under its timeout, retrying transient errors with the configured backoff.
+/// Returns the content sent back to the LLM and whether it is an error.
+async fn execute_with_retries(
+    tool: &dyn AgentTool,
+    tool_call: &genai::chat::ToolCall,
+    config: &AgentLoopConfig,
+    event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+    cancel: &tokio_util::sync::CancellationToken,
+) -> (String, bool) {
+    let timeout = tool.timeout().or(config.tool_timeout);
+    let mut retry = 0;
+
+    loop {
+        let execution = tool.execute(
+            &tool_call.call_id,
+            tool_call.fn_arguments.clone(),
+            event_tx,
+            cancel,
+        );
+        let result = match timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, execution).await {
+                Ok(result) => result,
+                Err(_) => return (timeout_error(&tool_call.fn_name, timeout, retry + 1), true),
+            },
+            None => execution.await,
+        };
+
+        match result {
+            Ok(result) => return (result, false),
+            Err(e) if crate::retry::is_transient(&e) && retry < config.tool_retry.max_retries => {
+                let delay = config.tool_retry.backoff(retry);
+                retry += 1;
+                log::warn!(
+                    "Tool '{}' failed with a transient error, retry {retry} in {delay:?}: {e}",
+                    tool_call.fn_name
+                );
+                let _ = event_tx.send(AgentEvent::ToolExecutionUpdate {
+                    call_id: tool_call.call_id.clone(),
+                    data: serde_json::json!({
+                        "retry": retry,
+                        "delay_ms": delay.as_millis() as u64,
+                        "error": e.to_string(),
+                    }),
+                });
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) => return (format!("Error executing tool: {e}"), true),
+        }
+    }
+}
+
+/// Structured tool error telling the LLM that a tool ran out of time.
+fn timeout_error(tool_name: &str, timeout: std::time::Duration, attempts: usize) -> String {
+    serde_json::json!({
+        "error": "timeout",
+        "message": format!(
+            "Tool '{tool_name}' did not finish within {} seconds",
+            timeout.as_secs_f64()
+        ),
+        "timeout_secs": timeout.as_secs_f64(),
+        "attempts": attempts,
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{EchoTool, run_loop, run_loop_cancelled_by};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn tool_call(call_id: &str, fn_name: &str) -> genai::chat::ToolCall {
+        genai::chat::ToolCall {
+            call_id: call_id.to_string(),
+            fn_name: fn_name.to_string(),
+            fn_arguments: serde_json::json!({ "id": "original" }),
+            thought_signatures: None,
+        }
+    }
+
+    /// A mutating `delete` and a read-only `lookup` echo tool, both taking a string `id` and
+    /// counting their runs in `runs`.
+    fn config_with(decision: ToolApproval, runs: &Arc<AtomicUsize>) -> AgentLoopConfig {
+        let echo = |name, read_only| EchoTool {
+            read_only,
+            schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": { "id": { "type": "string" } },
+                "required": ["id"],
+            })),
+            runs: runs.clone(),
+            ..EchoTool::new(name)
+        };
+        AgentLoopConfig {
+            tools: vec![
+                Box::new(echo("delete", false)),
+                Box::new(echo("lookup", true)),
+            ],
+            approval: Some(Box::new(move |_request| {
+                let decision = decision.clone();
+                Box::pin(async move { decision })
+            })),
+            ..Default::default()
+        }
+    }
+
+    async fn run(
+        config: &AgentLoopConfig,
+        calls: &[genai::chat::ToolCall],
+    ) -> (Vec<genai::chat::ToolResponse>, Vec<AgentEvent>) {
+        run_steered(config, calls, None).await
+    }
+
+    async fn run_steered(
+        config: &AgentLoopConfig,
+        calls: &[genai::chat::ToolCall],
+        steering: Option<tokio::sync::mpsc::UnboundedReceiver<genai::chat::ChatMessage>>,
+    ) -> (Vec<genai::chat::ToolResponse>, Vec<AgentEvent>) {
+        let (event_tx, mut event_rx) = tokio::sync::mpsc::unbounded_channel();
+        let calls: Vec<&genai::chat::ToolCall> = calls.iter().collect();
+        let ctx = AgentLoopContext {
+            messages: Vec::new(),
+            turn_index: 0,
+            steering,
+            structured_requests: 0,
+            structured_output: None,
+        };
+        let responses = execute_tool_calls(
+            &calls,
+            config,
+            &ctx,
+            &event_tx,
+            &tokio_util::sync::CancellationToken::new(),
+        )
+        .await
+        .expect("tool calls run");
+        drop(event_tx);
+        let mut events = Vec::new();
+        while let Some(event) = event_rx.recv().await {
+            events.push(event);
+        }
+        (responses, events)
+    }
+
+    #[test]
+    fn assistant_message_keeps_text_and_restores_thought_signatures() {
+        use genai::chat::{ContentPart, MessageContent};
+
+        let captured = MessageContent::from_text("Let me check.")
+            .append(ContentPart::ToolCall(tool_call("call_1", "lookup")));
+        let message = assistant_message(Some(captured), vec![String::from("sig")]);
+        let parts = message.content.parts();
+
+        assert!(matches!(&parts[0], ContentPart::ThoughtSignature(s) if s == "sig"));
+        assert!(matches!(&parts[1], ContentPart::Text(t) if t == "Let me check."));
+        assert_eq!(
+            message.content.tool_calls()[0].thought_signatures,
+            Some(vec![String::from("sig")])
+        );
+
+        let empty = assistant_message(None, Vec::new());
+        assert_eq!(empty.content.first_text(), Some(""));
+    }
+
+    #[test]
+    fn tool_call_deltas_accumulate_fragments_and_snapshots() {
+        let chunk =
+            |call_id: &str, fn_name: &str, arguments: serde_json::Value| genai::chat::ToolCall {
+                call_id: call_id.to_string(),
+                fn_name: fn_name.to_string(),
+                fn_arguments: arguments,
+                thought_signatures: None,
+            };
+        let partial = |event: AgentEvent| match event {
+            AgentEvent::ToolCallDelta {
+                tool_name,
+                partial_args,
+                ..
+            } => (tool_name, partial_args),
+            other => panic!("unexpected event {other:?}"),
+        };
+
+        let mut deltas = ToolCallDeltas::new(false);
+        // Fragments
+        deltas.push(chunk("call_1", "weather_forecast", "{\"mo".into()));
+        assert_eq!(
+            partial(deltas.push(chunk("call_1", "", "de\": 1}".into()))),
+            (
+                String::from("weather_forecast"),
+                String::from("{\"mode\": 1}")
+            )
+        );
+        // A fragment repeating the text received so far is still appended
+        deltas.push(chunk("call_4", "echo", "{\"text\": \"ab".into()));
+        assert_eq!(
+            partial(deltas.push(chunk("call_4", "", "{\"text\": \"ab".into()))).1,
+            "{\"text\": \"ab{\"text\": \"ab"
+        );
+
+        let mut deltas = ToolCallDeltas::new(true);
+        // Accumulated snapshots
+        deltas.push(chunk("call_2", "lookup", "{\"id".into()));
+        assert_eq!(
+            partial(deltas.push(chunk("call_2", "lookup", "{\"id\": 2}".into()))).1,
+            "{\"id\": 2}"
+        );
+        // Complete arguments
+        assert_eq!(
+            partial(deltas.push(chunk("call_3", "lookup", serde_json::json!({ "id": 3 })))).1,
+            r#"{"id":3}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn denied_calls_are_sent_back_as_errors() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let config = config_with(
+            ToolApproval::Deny {
+                reason: String::from("not today"),
+            },
+            &runs,
+        );
+        let (responses, events) = run(&config, &[tool_call("call_1", "delete")]).await;
+
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+        assert_eq!(
+            responses[0].content,
+            "Tool call denied by the user: not today"
+        );
+        assert!(matches!(
+            events[0],
+            AgentEvent::ToolApprovalRequested { ref tool_name, .. } if tool_name == "delete"
+        ));
+        assert!(matches!(
+            events[1],
+            AgentEvent::ToolExecutionStart { ref call_id, .. } if call_id == "call_1"
+        ));
+        assert!(matches!(
+            events[2],
+            AgentEvent::ToolExecutionEnd { is_error: true, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn edited_arguments_replace_the_original_ones() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let config = config_with(
+            ToolApproval::Edit {
+                arguments: serde_json::json!({ "id": "edited" }),
+            },
+            &runs,
+        );
+        let (responses, _) = run(&config, &[tool_call("call_1", "delete")]).await;
+
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert_eq!(responses[0].call_id, "call_1");
+        assert_eq!(responses[0].content, r#"{"id":"edited"}"#);
+    }
+
+    /// Fails on the first `failures` runs, then succeeds.
+    struct FlakyTool {
+        failures: usize,
+        transient: bool,
+        runs: Arc<AtomicUsize>,
+        delay: std::time::Duration,
+    }
+
+    #[async_trait::async_trait]
+    impl AgentTool for FlakyTool {
+        fn name(&self) -> &str {
+            "flaky"
+        }
+
+        fn definition(&self) -> genai::chat::Tool {
+            genai::chat::Tool::new("flaky")
+        }
+
+        fn is_read_only(&self) -> bool {
+            true
+        }
+
+        async fn execute(
+            &self,
+            _call_id: &str,
+            _arguments: serde_json::Value,
+            _event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+            _cancel: &tokio_util::sync::CancellationToken,
+        ) -> anyhow::Result<String> {
+            tokio::time::sleep(self.delay).await;
+            let run = self.runs.fetch_add(1, Ordering::SeqCst);
+            if run < self.failures {
+                let error = anyhow::anyhow!("503 Service Unavailable");
+                if self.transient {
+                    return Err(crate::TransientToolError::new(error).into());
+                }
+                return Err(error);
+            }
+            Ok(String::from("ok"))
+        }
+    }
+
+    fn flaky_config(tool: FlakyTool) -> AgentLoopConfig {
+        AgentLoopConfig {
+            tools: vec![Box::new(tool)],
+            tool_retry: crate::retry::RetryPolicy {
+                max_retries: 2,
+                initial_backoff: std::time::Duration::ZERO,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn transient_errors_are_retried() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let config = flaky_config(FlakyTool {
+            failures: 2,
+            transient: true,
+            runs: runs.clone(),
+            delay: std::time::Duration::ZERO,
+        });
+        let (responses, events) = run(&config, &[tool_call("call_1", "flaky")]).await;
+
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        assert_eq!(responses[0].content, "ok");
+        let retries = events
+            .iter()
+            .filter(|e| matches!(e, AgentEvent::ToolExecutionUpdate { .. }))
+            .count();
+        assert_eq!(retries, 2);
+    }
+
+    #[tokio::test]
+    async fn other_errors_are_not_retried() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let config = flaky_config(FlakyTool {
+            failures: 1,
+            transient: false,
+            runs: runs.clone(),
+            delay: std::time::Duration::ZERO,
+        });
+        let (responses, _) = run(&config, &[tool_call("call_1", "flaky")]).await;
+
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert!(responses[0].content.starts_with("Error executing tool"));
+    }
+
+    #[tokio::test]
+    async fn timeouts_are_reported_as_structured_errors() {
+        let config = AgentLoopConfig {
+            tool_timeout: Some(std::time::Duration::from_millis(10)),
+            ..flaky_config(FlakyTool {
+                failures: 0,
+                transient: false,
+                runs: Arc::new(AtomicUsize::new(0)),
+                delay: std::time::Duration::from_secs(5),
+            })
+        };
+        let (responses, events) = run(&config, &[tool_call("call_1", "flaky")]).await;
+
+        let error: serde_json::Value =
+            serde_json::from_str(&responses[0].content).expect("timeout errors are JSON");
+        assert_eq!(error["error"], "timeout");
+        assert_eq!(error["timeout_secs"], 0.01);
+        assert!(
+            events
+                .iter()
+                .any(|e| matches!(e, AgentEvent::ToolExecutionEnd { is_error: true, .. }))
+        );
+    }
+
+    #[tokio::test]
+    async fn read_only_calls_skip_approval() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let config = config_with(
+            ToolApproval::Deny {
+                reason: String::from("never"),
+            },
+            &runs,
+        );
+        let (responses, events) = run(&config, &[tool_call("call_1", "lookup")]).await;
+
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert_eq!(responses[0].content, r#"{"id":"original"}"#);
+        assert!(
+            !events
+                .iter()
+                .any(|e| matches!(e, AgentEvent::ToolApprovalRequested { .. }))
+        );
+    }
+
+    #[tokio::test]
+    async fn pending_steering_skips_mutating_calls() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let config = AgentLoopConfig {
+            skip_mutating_tools_on_steering: true,
+            ..config_with(ToolApproval::Approve, &runs)
+        };
+        let (steering_tx, steering_rx) = tokio::sync::mpsc::unbounded_channel();
+        steering_tx
+            .send(genai::chat::ChatMessage::user("actually, keep it"))
+            .expect("steering receiver is alive");
+
+        let (responses, events) = run_steered(
+            &config,
+            &[tool_call("call_1", "lookup"), tool_call("call_2", "delete")],
+            Some(steering_rx),
+        )
+        .await;
+
+        // The read-only lookup still runs, the delete is left for the model to reconsider
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert_eq!(responses[0].content, r#"{"id":"original"}"#);
+        assert!(responses[1].content.starts_with("Tool call skipped"));
+        assert!(events.iter().any(|e| matches!(
+            e,
+            AgentEvent::ToolExecutionEnd { call_id, is_error: true, .. } if call_id == "call_2"
+        )));
+    }
+
+    #[tokio::test]
+    async fn max_turns_stops_a_loop_that_keeps_calling_tools() {
+        use crate::ScriptedResponse;
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let config = AgentLoopConfig {
+            max_turns: 2,
+            ..config_with(ToolApproval::Approve, &runs)
+        };
+        let backend = crate::ScriptedBackend::new((0..5).map(|i| {
+            ScriptedResponse::tool_calls(vec![tool_call(&format!("call_{i}"), "lookup")])
+        }));
+
+        let (result, _) = run_loop(
+            &backend,
+            &config,
+            vec![genai::chat::ChatMessage::user("look it up")],
+        )
+        .await;
+
+        let messages = result.expect("the loop stops without error");
+        assert_eq!(backend.requests().len(), 2);
+        assert_eq!(backend.remaining(), 3);
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            messages.len(),
+            5,
+            "prompt, then a call and its result per turn"
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_arguments_go_back_to_the_model_without_running_the_tool() {
+        use crate::ScriptedResponse;
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let config = config_with(ToolApproval::Approve, &runs);
+        let backend = crate::ScriptedBackend::new([
+            ScriptedResponse::tool_calls(vec![genai::chat::ToolCall {
+                fn_arguments: serde_json::json!({ "id": 7 }),
+                ..tool_call("call_1", "lookup")
+            }]),
+            ScriptedResponse::text("sorry"),
+        ]);
+
+        let (result, events) = run_loop(
+            &backend,
+            &config,
+            vec![genai::chat::ChatMessage::user("look up 7")],
+        )
+        .await;
+        result.expect("the loop completes");
+
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+        assert!(events.iter().any(|e| matches!(
+            e,
+            AgentEvent::ToolExecutionEnd { call_id, is_error: true, .. } if call_id == "call_1"
+        )));
+        let requests = backend.requests();
+        let response = requests[1]
+            .request
+            .messages
+            .iter()
+            .flat_map(|m| m.content.tool_responses())
+            .next()
+            .expect("the error goes back to the model");
+        let error: serde_json::Value =
+            serde_json::from_str(&response.content).expect("the error is JSON");
+        assert_eq!(error["error"], "invalid_arguments");
+        assert_eq!(error["violations"][0]["field"], "/id");
+    }
+
+    #[tokio::test]
+    async fn read_only_calls_are_batched_between_mutating_calls() {
+        use crate::ScriptedResponse;
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let config = config_with(ToolApproval::Approve, &runs);
+        let backend = crate::ScriptedBackend::new([
+            ScriptedResponse::tool_calls(vec![
+                tool_call("call_1", "delete"),
+                tool_call("call_2", "lookup"),
+                tool_call("call_3", "lookup"),
+                tool_call("call_4", "delete"),
+            ]),
+            ScriptedResponse::text("done"),
+        ]);
+
+        let (result, events) = run_loop(
+            &backend,
+            &config,
+            vec![genai::chat::ChatMessage::user("clean up")],
+        )
+        .await;
+        result.expect("the loop completes");
+
+        let position = |wanted: &str, end: bool| {
+            events
+                .iter()
+                .position(|e| match e {
+                    AgentEvent::ToolExecutionStart { call_id, .. } => !end && call_id == wanted,
+                    AgentEvent::ToolExecutionEnd { call_id, .. } => end && call_id == wanted,
+                    _ => false,
+                })
+                .expect("the call ran")
+        };
+        assert!(position("call_1", true) < position("call_2", false));
+        assert!(position("call_2", false) < position("call_3", false));
+        assert!(position("call_3", true) < position("call_4", false));
+
+        // Results go back to the model in the order of the calls
+        let requests = backend.requests();
+        let call_ids: Vec<String> = requests[1]
+            .request
+            .messages
+            .iter()
+            .filter(|m| m.role == genai::chat::ChatRole::Tool)
+            .flat_map(|m| m.content.tool_responses())
+            .map(|r| r.call_id.clone())
+            .collect();
+        assert_eq!(call_ids, ["call_1", "call_2", "call_3", "call_4"]);
+    }
+
+    #[tokio::test]
+    async fn cancellation_aborts_a_stalled_stream() {
+        let backend = crate::ScriptedBackend::new([crate::ScriptedResponse::Hang]);
+        let cancel = tokio_util::sync::CancellationToken::new();
+        tokio::spawn({
+            let cancel = cancel.clone();
+            async move {
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                cancel.cancel();
+            }
+        });
+
+        let (result, events) = run_loop_cancelled_by(
+            &backend,
+            &AgentLoopConfig::default(),
+            vec![genai::chat::ChatMessage::user("hello")],
+            cancel,
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert!(
+            events
+                .iter()
+                .any(|e| matches!(e, AgentEvent::Aborted { phase, .. } if phase == "streaming"))
+        );
+        assert!(
+            !events
+                .iter()
+                .any(|e| matches!(e, AgentEvent::LlmRetry { .. }))
+        );
+    }
+
+    #[tokio::test]
+    async fn compaction_summarizes_before_the_turn_over_budget() {
+        use crate::ScriptedResponse;
+        use genai::chat::ChatMessage;
+
+        let config = AgentLoopConfig {
+            compaction: Some(crate::CompactionConfig {
+                token_budget: 50,
+                preserve_recent: 2,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let backend = crate::ScriptedBackend::new([
+            ScriptedResponse::text("## Facts\n- the user likes long questions").with_usage(300, 40),
+            ScriptedResponse::text("short answer"),
+        ]);
+        let long = "a long question about the weather ".repeat(10);
+        let history = vec![
+            ChatMessage::user(long.as_str()),
+            ChatMessage::assistant("first answer"),
+            ChatMessage::user(long.as_str()),
+            ChatMessage::assistant("second answer"),
+            ChatMessage::user("and now?"),
+        ];
+
+        let (result, events) = run_loop(&backend, &config, history).await;
+
+        let messages = result.expect("the loop completes");
+        let requests = backend.requests();
+        assert!(!requests[0].streamed, "the summary is requested first");
+        assert!(requests[1].streamed);
+        assert!(events.iter().any(|e| matches!(
+            e,
+            AgentEvent::CompactionEnd {
+                original_count: 5,
+                compacted_count: 3
+            }
+        )));
+        assert!(
+            events.iter().any(|e| matches!(
+                e,
+                AgentEvent::Usage { usage, .. } if usage.prompt_tokens == 300
+            )),
+            "the summary request counts in the usage"
+        );
+        assert_eq!(messages[0].role, genai::chat::ChatRole::System);
+        assert_eq!(requests[1].request.messages.len(), 3);
+        assert_eq!(
+            messages.last().and_then(|m| m.content.first_text()),
+            Some("short answer")
+        );
+    }
+
+    #[tokio::test]
+    async fn dropped_streams_are_retried() {
+        use crate::ScriptedResponse;
+
+        let config = AgentLoopConfig {
+            llm_retry: crate::retry::RetryPolicy {
+                max_retries: 1,
+                initial_backoff: std::time::Duration::ZERO,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let backend = crate::ScriptedBackend::new([
+            ScriptedResponse::dropped("Hel"),
+            ScriptedResponse::text("Hello"),
+        ]);
+
+        let (result, events) = run_loop(
+            &backend,
+            &config,
+            vec![genai::chat::ChatMessage::user("hi")],
+        )
+        .await;
+
+        let messages = result.expect("the retry succeeds");
+        assert_eq!(messages[1].content.first_text(), Some("Hello"));
+        assert_eq!(
+            events
+                .iter()
+                .filter(|e| matches!(e, AgentEvent::LlmRetry { .. }))
+                .count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use futures::StreamExt;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Events of a streamed chat response, see [`LlmBackend::exec_chat_stream`].
+pub type ChatEventStream =
+    futures::stream::BoxStream<'static, anyhow::Result<genai::chat::ChatStreamEvent>>;
+
+/// The chat provider behind the agent loop. `genai::Client` talks to the real providers,
+/// [`ScriptedBackend`] replays canned responses so the loop can be tested offline.
+///
+/// Errors keep their source: a `genai::Error` behind the `anyhow::Error` is what the loop
+/// looks at to decide whether a request is worth retrying.
+#[async_trait::async_trait]
+pub trait LlmBackend: Send + Sync {
+    /// Streamed chat completion, used for the assistant turns.
+    async fn exec_chat_stream(
+        &self,
+        model: &str,
+        request: genai::chat::ChatRequest,
+        options: Option<&genai::chat::ChatOptions>,
+    ) -> anyhow::Result<ChatEventStream>;
+
+    /// Chat completion in one piece, used to summarize the conversation on compaction.
+    async fn exec_chat(
+        &self,
+        model: &str,
+        request: genai::chat::ChatRequest,
+        options: Option<&genai::chat::ChatOptions>,
+    ) -> anyhow::Result<genai::chat::ChatResponse>;
+}
+
+#[async_trait::async_trait]
+impl LlmBackend for genai::Client {
+    async fn exec_chat_stream(
+        &self,
+        model: &str,
+        request: genai::chat::ChatRequest,
+        options: Option<&genai::chat::ChatOptions>,
+    ) -> anyhow::Result<ChatEventStream> {
+        let response = genai::Client::exec_chat_stream(self, model, request, options).await?;
+        Ok(response
+            .stream
+            .map(|event| event.map_err(anyhow::Error::from))
+            .boxed())
+    }
+
+    async fn exec_chat(
+        &self,
+        model: &str,
+        request: genai::chat::ChatRequest,
+        options: Option<&genai::chat::ChatOptions>,
+    ) -> anyhow::Result<genai::chat::ChatResponse> {
+        Ok(genai::Client::exec_chat(self, model, request, options).await?)
+    }
+}
+
+/// A response queued in a [`ScriptedBackend`].
+pub enum ScriptedResponse {
+    /// The events streamed back, in order. Without a final `End` event the stream is cut
+    /// short, like a dropped connection.
+    Stream(Vec<genai::chat::ChatStreamEvent>),
+    /// The request fails with this error.
+    Error(anyhow::Error),
+    /// The response never comes, until the loop gives up on it.
+    Hang,
+}
+
+impl ScriptedResponse {
+    /// An answer streamed in one chunk.
+    pub fn text(text: &str) -> Self {
+        Self::Stream(vec![
+            genai::chat::ChatStreamEvent::Start,
+            genai::chat::ChatStreamEvent::Chunk(genai::chat::StreamChunk {
+                content: text.to_string(),
+            }),
+            genai::chat::ChatStreamEvent::End(genai::chat::StreamEnd {
+                captured_content: Some(genai::chat::MessageContent::from_text(text)),
+                ..Default::default()
+            }),
+        ])
+    }
+
+    /// A response calling tools.
+    pub fn tool_calls(calls: Vec<genai::chat::ToolCall>) -> Self {
+        let mut events = vec![genai::chat::ChatStreamEvent::Start];
+        events.extend(calls.iter().map(|call| {
+            genai::chat::ChatStreamEvent::ToolCallChunk(genai::chat::ToolChunk {
+                tool_call: call.clone(),
+            })
+        }));
+        events.push(genai::chat::ChatStreamEvent::End(genai::chat::StreamEnd {
+            captured_content: Some(genai::chat::MessageContent::from_tool_calls(calls)),
+            ..Default::default()
+        }));
+        Self::Stream(events)
+    }
+
+    /// The same response, reporting `prompt_tokens` and `completion_tokens` at its end.
+    pub fn with_usage(mut self, prompt_tokens: i32, completion_tokens: i32) -> Self {
+        if let Self::Stream(events) = &mut self {
+            for event in events {
+                if let genai::chat::ChatStreamEvent::End(end) = event {
+                    end.captured_usage = Some(genai::chat::Usage {
+                        prompt_tokens: Some(prompt_tokens),
+                        completion_tokens: Some(completion_tokens),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+        self
+    }
+
+    /// Text streamed without the end of the response, as when the connection drops.
+    pub fn dropped(text: &str) -> Self {
+        Self::Stream(vec![
+            genai::chat::ChatStreamEvent::Start,
+            genai::chat::ChatStreamEvent::Chunk(genai::chat::StreamChunk {
+                content: text.to_string(),
+            }),
+        ])
+    }
+}
+
+/// A request received by a [`ScriptedBackend`].
+#[derive(Debug, Clone)]
+pub struct ScriptedRequest {
+    pub model: String,
+    pub request: genai::chat::ChatRequest,
+    /// Whether the request was streamed (an assistant turn) or not (a compaction summary).
+    pub streamed: bool,
+}
+
+/// An [`LlmBackend`] answering each request with the next queued [`ScriptedResponse`],
+/// whatever the request. Streamed and one-piece requests share the queue. A request
+/// arriving once the queue is empty fails.
+///
+/// Every request is recorded for the test to check what the loop sent.
+#[derive(Default)]
+pub struct ScriptedBackend {
+    responses: Mutex<VecDeque<ScriptedResponse>>,
+    requests: Mutex<Vec<ScriptedRequest>>,
+}
+
+impl ScriptedBackend {
+    pub fn new(responses: impl IntoIterator<Item = ScriptedResponse>) -> Self {
+        Self {
+            responses: Mutex::new(responses.into_iter().collect()),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Queue another response after the others.
+    pub fn push(&self, response: ScriptedResponse) {
+        if let Ok(mut responses) = self.responses.lock() {
+            responses.push_back(response);
+        }
+    }
+
+    /// The requests received so far, oldest first.
+    pub fn requests(&self) -> Vec<ScriptedRequest> {
+        self.requests
+            .lock()
+            .map(|requests| requests.clone())
+            .unwrap_or_default()
+    }
+
+    /// Responses not requested yet.
+    pub fn remaining(&self) -> usize {
+        self.responses
+            .lock()
+            .map(|responses| responses.len())
+            .unwrap_or_default()
+    }
+
+    fn next(
+        &self,
+        model: &str,
+        request: genai::chat::ChatRequest,
+        streamed: bool,
+    ) -> anyhow::Result<ScriptedResponse> {
+        self.requests
+            .lock()
+            .map_err(|e| anyhow::anyhow!("{e}"))?
+            .push(ScriptedRequest {
+                model: model.to_string(),
+                request,
+                streamed,
+            });
+        self.responses
+            .lock()
+            .map_err(|e| anyhow::anyhow!("{e}"))?
+            .pop_front()
+            .ok_or_else(|| anyhow::anyhow!("No scripted response left for model '{model}'"))
+    }
+}
+
+#[async_trait::async_trait]
+impl LlmBackend for ScriptedBackend {
+    async fn exec_chat_stream(
+        &self,
+        model: &str,
+        request: genai::chat::ChatRequest,
+        _options: Option<&genai::chat::ChatOptions>,
+    ) -> anyhow::Result<ChatEventStream> {
+        match self.next(model, request, true)? {
+            ScriptedResponse::Stream(events) => {
+                Ok(futures::stream::iter(events.into_iter().map(Ok)).boxed())
+            }
+            ScriptedResponse::Error(error) => Err(error),
+            ScriptedResponse::Hang => Ok(futures::stream::pending().boxed()),
+        }
+    }
+
+    async fn exec_chat(
+        &self,
+        model: &str,
+        request: genai::chat::ChatRequest,
+        _options: Option<&genai::chat::ChatOptions>,
+    ) -> anyhow::Result<genai::chat::ChatResponse> {
+        let events = match self.next(model, request, false)? {
+            ScriptedResponse::Stream(events) => events,
+            ScriptedResponse::Error(error) => return Err(error),
+            ScriptedResponse::Hang => std::future::pending().await,
+        };
+
+        Ok(chat_response(model, events))
+    }
+}
+
+/// The one-piece response made of streamed `events`: the content captured by the `End` event,
+/// or the chunks when the stream did not capture it.
+pub(crate) fn chat_response(
+    model: &str,
+    events: Vec<genai::chat::ChatStreamEvent>,
+) -> genai::chat::ChatResponse {
+    let mut chunks = String::new();
+    let mut captured = None;
+    let mut usage = None;
+    for event in events {
+        match event {
+            genai::chat::ChatStreamEvent::Chunk(chunk) => chunks.push_str(&chunk.content),
+            genai::chat::ChatStreamEvent::End(end) => {
+                captured = end.captured_content;
+                usage = end.captured_usage;
+            }
+            _ => {}
+        }
+    }
+    let model_iden = genai::ModelIden::new(genai::adapter::AdapterKind::OpenAI, model);
+    genai::chat::ChatResponse {
+        content: captured.unwrap_or_else(|| genai::chat::MessageContent::from_text(chunks)),
+        reasoning_content: None,
+        model_iden: model_iden.clone(),
+        provider_model_iden: model_iden,
+        usage: usage.unwrap_or_default(),
+        captured_raw_body: None,
+    }
+}
@@ -0,0 +1,911 @@
+use crate::agent_loop::{AgentEvent, AgentTool, ApprovalFn, ToolApproval};
+use crate::backend::{ChatEventStream, LlmBackend};
+use anyhow::Result;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Longest JSON value quoted in a divergence error.
+const DIVERGENCE_EXCERPT_CHARS: usize = 200;
+
+/// Everything an agent run got from the outside world: the LLM responses, the tool results
+/// and the approval decisions. Saved as JSON.
+///
+/// A [`CassetteRecorder`] fills it during a real run, a [`CassettePlayer`] plays it back
+/// without calling the LLM or running the tools.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Cassette {
+    pub runs: Vec<CassetteRun>,
+}
+
+impl Cassette {
+    pub fn load(path: &Path) -> Result<Self> {
+        let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// One agent loop: the messages it started from and what it received along the way.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CassetteRun {
+    pub initial_messages: Vec<genai::chat::ChatMessage>,
+    /// LLM requests in the order they were sent, with their responses.
+    pub llm_calls: Vec<LlmCall>,
+    /// Tool executions, one per attempt when a tool was retried.
+    pub tool_calls: Vec<ToolCallRecord>,
+    pub approvals: Vec<ApprovalRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LlmCall {
+    pub model: String,
+    /// Whether the response was streamed (an assistant turn) or not (a compaction summary).
+    pub streamed: bool,
+    pub request: genai::chat::ChatRequest,
+    /// Options the request was sent with, compared like the request on replay.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options: Option<genai::chat::ChatOptions>,
+    /// Events of the response. A one-piece response is kept as a single `End` event.
+    pub events: Vec<genai::chat::ChatStreamEvent>,
+    /// The error the request, or its stream after `events`, failed with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RecordedError>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedError {
+    pub message: String,
+    /// Whether the loop treated the error as transient, and retried it.
+    pub transient: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ToolCallRecord {
+    pub call_id: String,
+    pub tool_name: String,
+    /// Whether the tool was read-only, so a replay without the tool runs it the same way.
+    #[serde(default)]
+    pub read_only: bool,
+    pub arguments: serde_json::Value,
+    #[serde(flatten)]
+    pub result: ToolCallResult,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolCallResult {
+    Output(String),
+    Error(RecordedError),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApprovalRecord {
+    pub call_id: String,
+    pub decision: ToolApproval,
+}
+
+/// An LLM error played back from a cassette. The loop retries it when the recorded error was
+/// retried.
+#[derive(Debug)]
+pub(crate) struct ReplayedLlmError {
+    message: String,
+    pub(crate) transient: bool,
+}
+
+impl std::fmt::Display for ReplayedLlmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ReplayedLlmError {}
+
+impl From<RecordedError> for ReplayedLlmError {
+    fn from(error: RecordedError) -> Self {
+        Self {
+            message: error.message,
+            transient: error.transient,
+        }
+    }
+}
+
+/// `ChatStreamEvent` is not `Clone`, the recorder keeps its own copy through serde.
+fn copy_event(event: &genai::chat::ChatStreamEvent) -> Result<genai::chat::ChatStreamEvent> {
+    Ok(serde_json::from_value(serde_json::to_value(event)?)?)
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> Result<std::sync::MutexGuard<'_, T>> {
+    mutex
+        .lock()
+        .map_err(|e| anyhow::anyhow!("Cassette lock poisoned: {e}"))
+}
+
+/// Records an agent run into a [`Cassette`]. The backend, tools and approval callback of the
+/// run are wrapped with [`CassetteRecorder::backend`], [`CassetteRecorder::tools`] and
+/// [`CassetteRecorder::approval`], and each agent loop is announced with
+/// [`CassetteRecorder::start_run`].
+#[derive(Clone, Default)]
+pub struct CassetteRecorder {
+    cassette: Arc<Mutex<Cassette>>,
+}
+
+impl CassetteRecorder {
+    /// Starts recording a new agent loop, started from `initial_messages`.
+    pub fn start_run(&self, initial_messages: &[genai::chat::ChatMessage]) -> Result<()> {
+        lock(&self.cassette)?.runs.push(CassetteRun {
+            initial_messages: initial_messages.to_vec(),
+            ..Default::default()
+        });
+        Ok(())
+    }
+
+    pub fn backend(&self, inner: impl LlmBackend + 'static) -> RecordingBackend {
+        RecordingBackend {
+            inner: Box::new(inner),
+            cassette: self.cassette.clone(),
+        }
+    }
+
+    pub fn tools(&self, tools: Vec<Box<dyn AgentTool>>) -> Vec<Box<dyn AgentTool>> {
+        tools
+            .into_iter()
+            .map(|inner| {
+                Box::new(RecordingTool {
+                    inner,
+                    cassette: self.cassette.clone(),
+                }) as Box<dyn AgentTool>
+            })
+            .collect()
+    }
+
+    pub fn approval(&self, inner: ApprovalFn) -> ApprovalFn {
+        let inner = Arc::new(inner);
+        let cassette = self.cassette.clone();
+        Box::new(move |request| {
+            let inner = inner.clone();
+            let cassette = cassette.clone();
+            Box::pin(async move {
+                let call_id = request.call_id.clone();
+                let decision = inner(request).await;
+                if let Ok(mut cassette) = lock(&cassette) {
+                    current_run(&mut cassette).approvals.push(ApprovalRecord {
+                        call_id,
+                        decision: decision.clone(),
+                    });
+                }
+                decision
+            })
+        })
+    }
+
+    /// Writes what was recorded so far to `path`.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        lock(&self.cassette)?.save(path)
+    }
+}
+
+/// The run being recorded, started on the fly when the recorder was not told about it.
+fn current_run(cassette: &mut Cassette) -> &mut CassetteRun {
+    if cassette.runs.is_empty() {
+        cassette.runs.push(CassetteRun::default());
+    }
+    let last = cassette.runs.len() - 1;
+    &mut cassette.runs[last]
+}
+
+/// An [`LlmBackend`] recording the requests it forwards and the responses it gets back.
+pub struct RecordingBackend {
+    inner: Box<dyn LlmBackend>,
+    cassette: Arc<Mutex<Cassette>>,
+}
+
+impl RecordingBackend {
+    /// Adds the request to the current run, returns where its call is kept.
+    fn record_request(
+        &self,
+        model: &str,
+        request: &genai::chat::ChatRequest,
+        options: Option<&genai::chat::ChatOptions>,
+        streamed: bool,
+    ) -> Result<(usize, usize)> {
+        let mut cassette = lock(&self.cassette)?;
+        let run = current_run(&mut cassette);
+        run.llm_calls.push(LlmCall {
+            model: model.to_string(),
+            streamed,
+            request: request.clone(),
+            options: options.cloned(),
+            events: Vec::new(),
+            error: None,
+        });
+        Ok((cassette.runs.len() - 1, run_len(&cassette) - 1))
+    }
+}
+
+fn run_len(cassette: &Cassette) -> usize {
+    cassette.runs.last().map_or(0, |run| run.llm_calls.len())
+}
+
+fn update_call(
+    cassette: &Mutex<Cassette>,
+    (run, call): (usize, usize),
+    update: impl FnOnce(&mut LlmCall),
+) {
+    if let Ok(mut cassette) = lock(cassette)
+        && let Some(call) = cassette
+            .runs
+            .get_mut(run)
+            .and_then(|run| run.llm_calls.get_mut(call))
+    {
+        update(call);
+    }
+}
+
+fn recorded_error(error: &anyhow::Error) -> RecordedError {
+    RecordedError {
+        message: format!("{error:#}"),
+        transient: crate::retry::is_transient_llm_error(error),
+    }
+}
+
+#[async_trait::async_trait]
+impl LlmBackend for RecordingBackend {
+    async fn exec_chat_stream(
+        &self,
+        model: &str,
+        request: genai::chat::ChatRequest,
+        options: Option<&genai::chat::ChatOptions>,
+    ) -> Result<ChatEventStream> {
+        let index = self.record_request(model, &request, options, true)?;
+        let stream = match self.inner.exec_chat_stream(model, request, options).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                update_call(&self.cassette, index, |call| {
+                    call.error = Some(recorded_error(&e))
+                });
+                return Err(e);
+            }
+        };
+
+        let cassette = self.cassette.clone();
+        Ok(stream
+            .map(move |event| {
+                match &event {
+                    Ok(event) => match copy_event(event) {
+                        Ok(copy) => update_call(&cassette, index, |call| call.events.push(copy)),
+                        Err(e) => log::warn!("Failed to record a stream event: {e}"),
+                    },
+                    Err(e) => update_call(&cassette, index, |call| {
+                        call.error = Some(recorded_error(e))
+                    }),
+                }
+                event
+            })
+            .boxed())
+    }
+
+    async fn exec_chat(
+        &self,
+        model: &str,
+        request: genai::chat::ChatRequest,
+        options: Option<&genai::chat::ChatOptions>,
+    ) -> Result<genai::chat::ChatResponse> {
+        let index = self.record_request(model, &request, options, false)?;
+        let result = self.inner.exec_chat(model, request, options).await;
+        update_call(&self.cassette, index, |call| match &result {
+            Ok(response) => {
+                call.events
+                    .push(genai::chat::ChatStreamEvent::End(genai::chat::StreamEnd {
+                        captured_content: Some(response.content.clone()),
+                        ..Default::default()
+                    }))
+            }
+            Err(e) => call.error = Some(recorded_error(e)),
+        });
+        result
+    }
+}
+
+/// A tool recording each of its executions.
+struct RecordingTool {
+    inner: Box<dyn AgentTool>,
+    cassette: Arc<Mutex<Cassette>>,
+}
+
+#[async_trait::async_trait]
+impl AgentTool for RecordingTool {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn definition(&self) -> genai::chat::Tool {
+        self.inner.definition()
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.inner.is_read_only()
+    }
+
+    fn timeout(&self) -> Option<std::time::Duration> {
+        self.inner.timeout()
+    }
+
+    fn max_output_chars(&self) -> Option<usize> {
+        self.inner.max_output_chars()
+    }
+
+    async fn execute(
+        &self,
+        call_id: &str,
+        arguments: serde_json::Value,
+        event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+        cancel: &tokio_util::sync::CancellationToken,
+    ) -> Result<String> {
+        let result = self
+            .inner
+            .execute(call_id, arguments.clone(), event_tx, cancel)
+            .await;
+        let recorded = match &result {
+            Ok(output) => ToolCallResult::Output(output.clone()),
+            Err(e) => ToolCallResult::Error(RecordedError {
+                message: format!("{e:#}"),
+                transient: crate::retry::is_transient(e),
+            }),
+        };
+        if let Ok(mut cassette) = lock(&self.cassette) {
+            current_run(&mut cassette).tool_calls.push(ToolCallRecord {
+                call_id: call_id.to_string(),
+                tool_name: self.inner.name().to_string(),
+                read_only: self.inner.is_read_only(),
+                arguments,
+                result: recorded,
+            });
+        }
+        result
+    }
+}
+
+/// Plays a [`Cassette`] back. The LLM responses, tool results and approval decisions all come
+/// from the cassette, nothing leaves the machine and no tool runs.
+///
+/// Every request must match the recorded one, model, messages, system prompt, tool
+/// definitions and options included. The first request that differs fails with the place
+/// where it diverges, which is what makes cassettes regression tests for prompt and tool
+/// changes. Steering messages are not recorded, runs that were steered diverge on replay.
+#[derive(Clone)]
+pub struct CassettePlayer {
+    state: Arc<Mutex<PlayerState>>,
+}
+
+struct PlayerState {
+    runs: VecDeque<CassetteRun>,
+    current: Option<PlayedRun>,
+    /// Runs started so far.
+    run_count: usize,
+}
+
+struct PlayedRun {
+    llm_calls: VecDeque<LlmCall>,
+    /// Requests played so far in the run.
+    played: usize,
+    tool_calls: Vec<ToolCallRecord>,
+    approvals: Vec<ApprovalRecord>,
+}
+
+impl CassettePlayer {
+    pub fn new(cassette: Cassette) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(PlayerState {
+                runs: cassette.runs.into(),
+                current: None,
+                run_count: 0,
+            })),
+        }
+    }
+
+    /// Moves to the next recorded run and returns the messages to start its agent loop from,
+    /// or `None` once every run was played. Fails when the previous run did not send all of
+    /// its recorded requests.
+    pub fn next_run(&self) -> Result<Option<Vec<genai::chat::ChatMessage>>> {
+        let mut state = lock(&self.state)?;
+        state.check_run_finished()?;
+        let Some(run) = state.runs.pop_front() else {
+            state.current = None;
+            return Ok(None);
+        };
+        state.run_count += 1;
+        state.current = Some(PlayedRun {
+            llm_calls: run.llm_calls.into(),
+            played: 0,
+            tool_calls: run.tool_calls,
+            approvals: run.approvals,
+        });
+        Ok(Some(run.initial_messages))
+    }
+
+    /// Fails unless every recorded run and request was played.
+    pub fn finish(&self) -> Result<()> {
+        let state = lock(&self.state)?;
+        state.check_run_finished()?;
+        anyhow::ensure!(
+            state.runs.is_empty(),
+            "{} recorded runs were not replayed",
+            state.runs.len()
+        );
+        Ok(())
+    }
+
+    pub fn backend(&self) -> ReplayBackend {
+        ReplayBackend {
+            state: self.state.clone(),
+        }
+    }
+
+    /// The tools of the run, answering from the cassette instead of executing. Their
+    /// definitions are still sent, so they must be the tools the run was recorded with.
+    ///
+    /// Recorded tools missing from `tools`, such as the tools of MCP servers not connected for
+    /// the replay, are added after them with their recorded definitions.
+    pub fn tools(&self, tools: Vec<Box<dyn AgentTool>>) -> Vec<Box<dyn AgentTool>> {
+        let recorded = lock(&self.state)
+            .map(|state| state.recorded_tools())
+            .unwrap_or_default();
+        let missing: Vec<Box<dyn AgentTool>> = recorded
+            .into_iter()
+            .filter(|(definition, _)| tools.iter().all(|tool| tool.name() != definition.name))
+            .map(|(definition, read_only)| {
+                Box::new(RecordedTool {
+                    definition,
+                    read_only,
+                }) as Box<dyn AgentTool>
+            })
+            .collect();
+        tools
+            .into_iter()
+            .chain(missing)
+            .map(|inner| {
+                Box::new(ReplayTool {
+                    inner,
+                    state: self.state.clone(),
+                }) as Box<dyn AgentTool>
+            })
+            .collect()
+    }
+
+    /// An approval callback giving the recorded decisions.
+    pub fn approval(&self) -> ApprovalFn {
+        let state = self.state.clone();
+        Box::new(move |request| {
+            let decision = lock(&state).ok().and_then(|mut state| {
+                let run = state.current.as_mut()?;
+                let index = run
+                    .approvals
+                    .iter()
+                    .position(|record| record.call_id == request.call_id)?;
+                Some(run.approvals.remove(index).decision)
+            });
+            Box::pin(async move {
+                decision.unwrap_or_else(|| ToolApproval::Deny {
+                    reason: String::from("no approval recorded in the cassette"),
+                })
+            })
+        })
+    }
+}
+
+impl PlayerState {
+    /// Tool definitions sent in the runs left to play, in the order they first appear, and
+    /// whether the recorded calls of each tool were read-only.
+    fn recorded_tools(&self) -> Vec<(genai::chat::Tool, bool)> {
+        let mut tools: Vec<(genai::chat::Tool, bool)> = Vec::new();
+        for run in &self.runs {
+            let definitions = run
+                .llm_calls
+                .iter()
+                .flat_map(|call| call.request.tools.iter().flatten());
+            for definition in definitions {
+                if tools.iter().all(|(tool, _)| tool.name != definition.name) {
+                    tools.push((definition.clone(), false));
+                }
+            }
+        }
+        for (definition, read_only) in &mut tools {
+            *read_only = self
+                .runs
+                .iter()
+                .flat_map(|run| &run.tool_calls)
+                .any(|record| record.tool_name == definition.name && record.read_only);
+        }
+        tools
+    }
+
+    fn check_run_finished(&self) -> Result<()> {
+        if let Some(run) = &self.current {
+            anyhow::ensure!(
+                run.llm_calls.is_empty(),
+                "Run {} diverges from the cassette: it ended after {} requests, {} more were recorded",
+                self.run_count,
+                run.played,
+                run.llm_calls.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// The recorded call matching this request, or the divergence.
+    fn play(
+        &mut self,
+        model: &str,
+        request: &genai::chat::ChatRequest,
+        options: Option<&genai::chat::ChatOptions>,
+        streamed: bool,
+    ) -> Result<LlmCall> {
+        let run_count = self.run_count;
+        let run = self
+            .current
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("No cassette run started, call `next_run` first"))?;
+        run.played += 1;
+        let position = format!("Request {} of run {run_count}", run.played);
+        let call = run.llm_calls.pop_front().ok_or_else(|| {
+            anyhow::anyhow!("{position} diverges from the cassette: no more requests were recorded")
+        })?;
+
+        anyhow::ensure!(
+            call.model == model && call.streamed == streamed,
+            "{position} diverges from the cassette: recorded a {} request to '{}', got a {} request to '{model}'",
+            if call.streamed {
+                "streamed"
+            } else {
+                "one-piece"
+            },
+            call.model,
+            if streamed { "streamed" } else { "one-piece" },
+        );
+        let recorded = serde_json::json!({ "request": call.request, "options": call.options });
+        let actual = serde_json::json!({ "request": request, "options": options });
+        if let Some((pointer, recorded, actual)) = first_difference(&recorded, &actual, "") {
+            anyhow::bail!(
+                "{position} diverges from the cassette at {pointer}: recorded {}, got {}",
+                excerpt(recorded),
+                excerpt(actual)
+            );
+        }
+        Ok(call)
+    }
+}
+
+/// JSON pointer of the first place where `recorded` and `actual` differ, with both values.
+fn first_difference<'a>(
+    recorded: &'a serde_json::Value,
+    actual: &'a serde_json::Value,
+    pointer: &str,
+) -> Option<(
+    String,
+    Option<&'a serde_json::Value>,
+    Option<&'a serde_json::Value>,
+)> {
+    use serde_json::Value;
+
+    match (recorded, actual) {
+        (Value::Object(recorded), Value::Object(actual)) => {
+            let mut keys: Vec<&String> = recorded.keys().chain(actual.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            keys.into_iter().find_map(|key| {
+                let pointer = format!("{pointer}/{key}");
+                match (recorded.get(key), actual.get(key)) {
+                    (Some(recorded), Some(actual)) => first_difference(recorded, actual, &pointer),
+                    (recorded, actual) => Some((pointer, recorded, actual)),
+                }
+            })
+        }
+        (Value::Array(recorded), Value::Array(actual)) => (0..recorded.len().max(actual.len()))
+            .find_map(|index| {
+                let pointer = format!("{pointer}/{index}");
+                match (recorded.get(index), actual.get(index)) {
+                    (Some(recorded), Some(actual)) => first_difference(recorded, actual, &pointer),
+                    (recorded, actual) => Some((pointer, recorded, actual)),
+                }
+            }),
+        _ if recorded == actual => None,
+        _ => Some((
+            match pointer {
+                "" => String::from("/"),
+                pointer => pointer.to_string(),
+            },
+            Some(recorded),
+            Some(actual),
+        )),
+    }
+}
+
+fn excerpt(value: Option<&serde_json::Value>) -> String {
+    let Some(value) = value else {
+        return String::from("nothing");
+    };
+    let text = value.to_string();
+    if text.chars().count() > DIVERGENCE_EXCERPT_CHARS {
+        let truncated: String = text.chars().take(DIVERGENCE_EXCERPT_CHARS).collect();
+        format!("{truncated}...")
+    } else {
+        text
+    }
+}
+
+/// An [`LlmBackend`] answering from a [`CassettePlayer`].
+pub struct ReplayBackend {
+    state: Arc<Mutex<PlayerState>>,
+}
+
+#[async_trait::async_trait]
+impl LlmBackend for ReplayBackend {
+    async fn exec_chat_stream(
+        &self,
+        model: &str,
+        request: genai::chat::ChatRequest,
+        options: Option<&genai::chat::ChatOptions>,
+    ) -> Result<ChatEventStream> {
+        let call = lock(&self.state)?.play(model, &request, options, true)?;
+        if call.events.is_empty()
+            && let Some(error) = call.error
+        {
+            return Err(ReplayedLlmError::from(error).into());
+        }
+        let failure = call
+            .error
+            .map(|error| Err(ReplayedLlmError::from(error).into()));
+        Ok(futures::stream::iter(call.events.into_iter().map(Ok).chain(failure)).boxed())
+    }
+
+    async fn exec_chat(
+        &self,
+        model: &str,
+        request: genai::chat::ChatRequest,
+        options: Option<&genai::chat::ChatOptions>,
+    ) -> Result<genai::chat::ChatResponse> {
+        let call = lock(&self.state)?.play(model, &request, options, false)?;
+        if let Some(error) = call.error {
+            return Err(ReplayedLlmError::from(error).into());
+        }
+        Ok(crate::backend::chat_response(model, call.events))
+    }
+}
+
+/// A recorded tool that is not available for the replay, played from its definition. Its
+/// calls are answered by the [`ReplayTool`] around it.
+struct RecordedTool {
+    definition: genai::chat::Tool,
+    read_only: bool,
+}
+
+#[async_trait::async_trait]
+impl AgentTool for RecordedTool {
+    fn name(&self) -> &str {
+        &self.definition.name
+    }
+
+    fn definition(&self) -> genai::chat::Tool {
+        self.definition.clone()
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    async fn execute(
+        &self,
+        _call_id: &str,
+        _arguments: serde_json::Value,
+        _event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+        _cancel: &tokio_util::sync::CancellationToken,
+    ) -> Result<String> {
+        anyhow::bail!("Tool '{}' is only known from the cassette", self.name())
+    }
+}
+
+/// A tool answering with the result recorded for the same call.
+struct ReplayTool {
+    inner: Box<dyn AgentTool>,
+    state: Arc<Mutex<PlayerState>>,
+}
+
+#[async_trait::async_trait]
+impl AgentTool for ReplayTool {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn definition(&self) -> genai::chat::Tool {
+        self.inner.definition()
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.inner.is_read_only()
+    }
+
+    fn timeout(&self) -> Option<std::time::Duration> {
+        self.inner.timeout()
+    }
+
+    fn max_output_chars(&self) -> Option<usize> {
+        self.inner.max_output_chars()
+    }
+
+    async fn execute(
+        &self,
+        call_id: &str,
+        arguments: serde_json::Value,
+        _event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+        _cancel: &tokio_util::sync::CancellationToken,
+    ) -> Result<String> {
+        let record = {
+            let mut state = lock(&self.state)?;
+            let run = state
+                .current
+                .as_mut()
+                .ok_or_else(|| anyhow::anyhow!("No cassette run started"))?;
+            let index = run
+                .tool_calls
+                .iter()
+                .position(|record| record.call_id == call_id && record.tool_name == self.name())
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Tool call '{call_id}' to '{}' is not in the cassette",
+                        self.name()
+                    )
+                })?;
+            run.tool_calls.remove(index)
+        };
+        anyhow::ensure!(
+            record.arguments == arguments,
+            "Tool call '{call_id}' diverges from the cassette: recorded arguments {}, got {arguments}",
+            record.arguments
+        );
+        match record.result {
+            ToolCallResult::Output(output) => Ok(output),
+            ToolCallResult::Error(error) if error.transient => {
+                Err(crate::retry::TransientToolError::new(anyhow::anyhow!(error.message)).into())
+            }
+            ToolCallResult::Error(error) => Err(anyhow::anyhow!(error.message)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{EchoTool, run_loop};
+    use crate::{AgentLoopConfig, ScriptedBackend, ScriptedResponse};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn config(tools: Vec<Box<dyn AgentTool>>, system_prompt: &str) -> AgentLoopConfig {
+        AgentLoopConfig {
+            system_prompt: system_prompt.to_string(),
+            tools,
+            ..Default::default()
+        }
+    }
+
+    /// Records a run calling the `count` echo tool once, returns the cassette and the messages.
+    async fn record() -> (Cassette, Vec<genai::chat::ChatMessage>) {
+        let recorder = CassetteRecorder::default();
+        let backend = recorder.backend(ScriptedBackend::new([
+            ScriptedResponse::tool_calls(vec![genai::chat::ToolCall {
+                call_id: String::from("call_1"),
+                fn_name: String::from("count"),
+                fn_arguments: serde_json::json!({}),
+                thought_signatures: None,
+            }]),
+            ScriptedResponse::text("Counted once."),
+        ]));
+        let tools = recorder.tools(vec![Box::new(EchoTool::new("count"))]);
+        let prompt = vec![genai::chat::ChatMessage::user("count")];
+        recorder.start_run(&prompt).expect("run starts");
+        let messages = run_loop(&backend, &config(tools, "Be brief."), prompt)
+            .await
+            .0
+            .expect("recorded run completes");
+
+        // Through JSON, like a cassette file
+        let json = serde_json::to_string(&*lock(&recorder.cassette).expect("lock"))
+            .expect("cassette serializes");
+        let cassette = serde_json::from_str(&json).expect("cassette deserializes");
+        (cassette, messages)
+    }
+
+    #[tokio::test]
+    async fn replays_reproduce_the_run_without_running_tools() {
+        let (cassette, recorded) = record().await;
+        let player = CassettePlayer::new(cassette);
+        let runs = Arc::new(AtomicUsize::new(0));
+        let tools = player.tools(vec![Box::new(EchoTool {
+            runs: runs.clone(),
+            ..EchoTool::new("count")
+        })]);
+        let config = config(tools, "Be brief.");
+
+        let initial = player
+            .next_run()
+            .expect("first run")
+            .expect("one run was recorded");
+        let replayed = run_loop(&player.backend(), &config, initial)
+            .await
+            .0
+            .expect("replay completes");
+
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+        assert_eq!(
+            serde_json::to_value(&replayed).expect("messages serialize"),
+            serde_json::to_value(&recorded).expect("messages serialize")
+        );
+        assert!(player.next_run().expect("no divergence").is_none());
+        player.finish().expect("everything was played");
+    }
+
+    #[tokio::test]
+    async fn tools_missing_from_the_replay_are_played_from_the_cassette() {
+        let (cassette, recorded) = record().await;
+        let player = CassettePlayer::new(cassette);
+        let config = config(player.tools(Vec::new()), "Be brief.");
+
+        let initial = player.next_run().expect("first run").expect("one run");
+        let replayed = run_loop(&player.backend(), &config, initial)
+            .await
+            .0
+            .expect("replay completes without the tool");
+        assert_eq!(
+            serde_json::to_value(&replayed).expect("messages serialize"),
+            serde_json::to_value(&recorded).expect("messages serialize")
+        );
+        player.finish().expect("everything was played");
+    }
+
+    #[tokio::test]
+    async fn replays_fail_where_the_requests_diverge() {
+        let (cassette, _) = record().await;
+        let player = CassettePlayer::new(cassette);
+        let tools = player.tools(vec![Box::new(EchoTool::new("count"))]);
+        let config = config(tools, "Be thorough.");
+
+        let initial = player.next_run().expect("first run").expect("one run");
+        let error = run_loop(&player.backend(), &config, initial)
+            .await
+            .0
+            .expect_err("the system prompt changed");
+        assert!(
+            error
+                .to_string()
+                .contains("diverges from the cassette at /request/system"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn replays_fail_when_the_options_change() {
+        let (cassette, _) = record().await;
+        let player = CassettePlayer::new(cassette);
+        let tools = player.tools(Vec::new());
+        let mut config = config(tools, "Be brief.");
+        config.chat_options = config.chat_options.with_temperature(0.2);
+
+        let initial = player.next_run().expect("first run").expect("one run");
+        let error = run_loop(&player.backend(), &config, initial)
+            .await
+            .0
+            .expect_err("the temperature changed");
+        assert!(
+            error
+                .to_string()
+                .contains("diverges from the cassette at /options/temperature"),
+            "{error}"
+        );
+    }
+}
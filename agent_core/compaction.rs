@@ -1,3 +1,97 @@
+use std::sync::Arc;
+
+/// Tokens added by the chat format around every message (role, separators).
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+
+/// Flat estimate for binary parts (images, files), whose size in tokens is provider specific.
+const BINARY_PART_TOKENS: usize = 1_000;
+
+/// Counts the tokens a message takes in the model's context window.
+pub trait TokenCounter: std::fmt::Debug + Send + Sync {
+    /// Number of tokens in a piece of text.
+    fn count_text(&self, text: &str) -> usize;
+
+    /// Number of tokens in a message: its text, tool calls (name and arguments) and tool
+    /// results, plus the per-message overhead of the chat format.
+    fn count_message(&self, message: &genai::chat::ChatMessage) -> usize {
+        use genai::chat::ContentPart;
+
+        let content: usize = message
+            .content
+            .parts()
+            .iter()
+            .map(|part| match part {
+                ContentPart::Text(text) => self.count_text(text),
+                ContentPart::ToolCall(call) => {
+                    self.count_text(&call.fn_name) + self.count_text(&call.fn_arguments.to_string())
+                }
+                ContentPart::ToolResponse(response) => self.count_text(&response.content),
+                ContentPart::Binary(_) => BINARY_PART_TOKENS,
+                ContentPart::ThoughtSignature(_) => 0,
+            })
+            .sum();
+        content + MESSAGE_OVERHEAD_TOKENS
+    }
+}
+
+/// Estimates ~4 characters per token, a reasonable average across English text for most
+/// tokenizers (GPT, Claude, etc.). Imprecise, but free and model agnostic.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeuristicTokenCounter;
+
+impl TokenCounter for HeuristicTokenCounter {
+    fn count_text(&self, text: &str) -> usize {
+        text.len().div_ceil(4)
+    }
+}
+
+/// Counts tokens with an OpenAI BPE vocabulary. The vocabularies are bundled in the binary,
+/// no download is needed. Exact for OpenAI models, a close estimate for other providers.
+pub struct BpeTokenCounter {
+    encoding: String,
+    bpe: &'static tiktoken_rs::CoreBPE,
+}
+
+impl BpeTokenCounter {
+    /// The `o200k_base` vocabulary used by the gpt-4o, gpt-4.1 and o-series models.
+    pub fn o200k() -> Self {
+        Self {
+            encoding: String::from("o200k_base"),
+            bpe: tiktoken_rs::o200k_base_singleton(),
+        }
+    }
+
+    /// The `cl100k_base` vocabulary used by the gpt-4 and gpt-3.5 models.
+    pub fn cl100k() -> Self {
+        Self {
+            encoding: String::from("cl100k_base"),
+            bpe: tiktoken_rs::cl100k_base_singleton(),
+        }
+    }
+
+    /// The vocabulary of an OpenAI model, e.g. "gpt-4o". Fails for models of other providers.
+    pub fn for_model(model: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            encoding: model.to_string(),
+            bpe: tiktoken_rs::bpe_for_model(model)?,
+        })
+    }
+}
+
+impl std::fmt::Debug for BpeTokenCounter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BpeTokenCounter")
+            .field("encoding", &self.encoding)
+            .finish()
+    }
+}
+
+impl TokenCounter for BpeTokenCounter {
+    fn count_text(&self, text: &str) -> usize {
+        self.bpe.encode_ordinary(text).len()
+    }
+}
+
 /// Configuration for automatic context compaction.
 #[derive(Debug, Clone)]
 pub struct CompactionConfig {
@@ -10,6 +104,10 @@ pub struct CompactionConfig {
     /// These are the most recent messages that the model needs full detail on.
     /// Default: 6 (approximately 3 user+assistant turn pairs).
     pub preserve_recent: usize,
+
+    /// Counts the tokens of the conversation against `token_budget`.
+    /// Default: [`HeuristicTokenCounter`].
+    pub token_counter: Arc<dyn TokenCounter>,
 }
 
 impl Default for CompactionConfig {
@@ -17,24 +115,34 @@ impl Default for CompactionConfig {
         Self {
             token_budget: 80_000,
             preserve_recent: 6,
+            token_counter: Arc::new(HeuristicTokenCounter),
         }
     }
 }
 
-/// Rough token estimate for a single ChatMessage.
-///
-/// Uses the heuristic of ~4 characters per token, which is a reasonable
-/// average across English text for most tokenizers (GPT, Claude, etc.).
-/// This is intentionally imprecise — compaction is a soft threshold,
-/// not a hard limit.
-fn estimate_message_tokens(message: &genai::chat::ChatMessage) -> usize {
-    let text = serde_json::to_string(&message.content).unwrap_or_default();
-    text.len() / 4
+impl CompactionConfig {
+    /// Budget for a model with a `context_window` of tokens, keeping `reserved` tokens free
+    /// for the system prompt, the tool definitions and the next response.
+    pub fn for_context_window(context_window: usize, reserved: usize) -> Self {
+        Self {
+            token_budget: context_window.saturating_sub(reserved),
+            ..Default::default()
+        }
+    }
+
+    /// Use `token_counter` instead of the heuristic.
+    pub fn with_token_counter(mut self, token_counter: impl TokenCounter + 'static) -> Self {
+        self.token_counter = Arc::new(token_counter);
+        self
+    }
 }
 
 /// Estimate the total token count across all messages.
-pub fn estimate_total_tokens(messages: &[genai::chat::ChatMessage]) -> usize {
-    messages.iter().map(|m| estimate_message_tokens(m)).sum()
+pub fn estimate_total_tokens(
+    messages: &[genai::chat::ChatMessage],
+    counter: &dyn TokenCounter,
+) -> usize {
+    messages.iter().map(|m| counter.count_message(m)).sum()
 }
 
 /// Returns true if the estimated token count exceeds the budget.
@@ -42,51 +150,53 @@ pub fn should_compact(messages: &[genai::chat::ChatMessage], config: &Compaction
     if messages.len() <= config.preserve_recent {
         return false;
     }
-    estimate_total_tokens(messages) > config.token_budget
+    estimate_total_tokens(messages, config.token_counter.as_ref()) > config.token_budget
 }
 
+/// Start of the system message holding the conversation summary.
+const SUMMARY_HEADER: &str = "[Conversation summary — compacted from ";
+
+/// Sections every summary is made of, in order.
+const SUMMARY_SECTIONS: [&str; 4] = ["Facts", "Decisions", "Open tasks", "Tool outputs"];
+
 /// Compact the conversation by summarizing old messages.
 ///
-/// Splits the message list into two halves:
+/// Splits the message list into two parts:
 /// - Old messages (everything before the last `preserve_recent`): summarized by the LLM.
-/// - Recent messages (last `preserve_recent`): kept verbatim.
+/// - Recent messages (last `preserve_recent`): kept verbatim. The split moves back so that
+///   tool responses are never separated from the assistant message that called the tools.
+///
+/// When the conversation already starts with the summary of an earlier compaction, that summary
+/// is updated with the old messages instead of being summarized again.
 ///
-/// The summary replaces all old messages with a single system message containing
-/// the condensed conversation history.
+/// The summary replaces all old messages with a single system message made of the sections
+/// of `SUMMARY_SECTIONS`.
 ///
-/// Returns the compacted message list: [summary_system_message, ...recent_messages].
+/// Returns the compacted message list: [summary_system_message, ...recent_messages], with the
+/// tokens of the summary request when the provider reported them.
 pub async fn compact(
-    client: &genai::Client,
+    client: &dyn crate::backend::LlmBackend,
     model: &str,
     messages: &[genai::chat::ChatMessage],
     config: &CompactionConfig,
-) -> anyhow::Result<Vec<genai::chat::ChatMessage>> {
+) -> anyhow::Result<(
+    Vec<genai::chat::ChatMessage>,
+    Option<crate::usage::TokenUsage>,
+)> {
     use genai::chat::{ChatMessage, ChatRequest};
 
-    if messages.len() <= config.preserve_recent {
-        return Ok(messages.to_vec());
+    let previous = previous_summary(messages);
+    let summarized_start = usize::from(previous.is_some());
+    let split_point = split_point(messages, config.preserve_recent);
+    if split_point <= summarized_start {
+        return Ok((messages.to_vec(), None));
     }
 
-    let split_point = messages.len() - config.preserve_recent;
-    let old_messages = &messages[..split_point];
+    let old_messages = &messages[summarized_start..split_point];
     let recent_messages = &messages[split_point..];
 
-    let summary_system = ChatMessage::system(
-        "You are a conversation summarizer. Summarize the following conversation history \
-         into a concise but complete summary. Preserve:\n\
-         - All key facts, decisions, and conclusions reached\n\
-         - Tool call results and their outcomes\n\
-         - Any commitments, plans, or action items discussed\n\
-         - User preferences or corrections mentioned\n\n\
-         Be concise but do not omit important details. Output only the summary, \
-         no preamble.",
-    );
-
-    let mut summary_messages = vec![summary_system];
-    summary_messages.extend_from_slice(old_messages);
-    summary_messages.push(ChatMessage::user("Summarize the conversation above."));
-
-    let summary_request = ChatRequest::from_messages(summary_messages);
+    let summary_request =
+        ChatRequest::from_messages(summary_prompt(previous.map(|(_, s)| s), old_messages));
     let summary_response = client
         .exec_chat(model, summary_request, None)
         .await
@@ -94,13 +204,13 @@ pub async fn compact(
 
     let summary_text = summary_response
         .first_text()
-        .unwrap_or("[Compaction failed: no summary text returned]");
+        .ok_or_else(|| anyhow::anyhow!("Compaction LLM call returned no summary text"))?;
 
+    let summarized_count = previous.map_or(0, |(count, _)| count) + old_messages.len();
     let mut compacted = Vec::with_capacity(1 + recent_messages.len());
     compacted.push(ChatMessage::system(format!(
-        "[Conversation summary — compacted from {} messages]\n\n{}",
-        old_messages.len(),
-        summary_text
+        "{SUMMARY_HEADER}{summarized_count} messages]\n\n{}",
+        normalize_summary(summary_text)
     )));
     compacted.extend_from_slice(recent_messages);
 
@@ -111,5 +221,227 @@ pub async fn compact(
         old_messages.len(),
     );
 
-    Ok(compacted)
+    let usage = &summary_response.usage;
+    let usage = (usage.prompt_tokens.is_some() || usage.completion_tokens.is_some())
+        .then(|| crate::usage::TokenUsage::from(usage));
+    Ok((compacted, usage))
+}
+
+/// Index of the first message kept verbatim.
+///
+/// Starts `preserve_recent` messages from the end and moves back over tool responses, so the
+/// assistant message holding the tool calls stays with its responses.
+fn split_point(messages: &[genai::chat::ChatMessage], preserve_recent: usize) -> usize {
+    let mut split_point = messages.len().saturating_sub(preserve_recent);
+    while split_point > 0
+        && messages
+            .get(split_point)
+            .is_some_and(|m| m.role == genai::chat::ChatRole::Tool)
+    {
+        split_point -= 1;
+    }
+    split_point
+}
+
+/// The summary left by an earlier compaction, with the number of messages it covers.
+fn previous_summary(messages: &[genai::chat::ChatMessage]) -> Option<(usize, &str)> {
+    let first = messages.first()?;
+    if first.role != genai::chat::ChatRole::System {
+        return None;
+    }
+    let (count, summary) = first
+        .content
+        .first_text()?
+        .strip_prefix(SUMMARY_HEADER)?
+        .split_once(" messages]\n\n")?;
+    Some((count.parse().ok()?, summary))
+}
+
+/// Messages asking the LLM to summarize `old_messages`, or to fold them into `previous`.
+///
+/// The old messages are sent as a plain transcript: some providers reject tool calls and
+/// tool responses in a request that does not declare the tools.
+fn summary_prompt(
+    previous: Option<&str>,
+    old_messages: &[genai::chat::ChatMessage],
+) -> Vec<genai::chat::ChatMessage> {
+    use genai::chat::ChatMessage;
+
+    let sections = SUMMARY_SECTIONS
+        .iter()
+        .map(|section| format!("## {section}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let system = ChatMessage::system(format!(
+        "You are a conversation summarizer. Summarize the conversation you are given into a \
+         concise but complete summary made of exactly these sections, in this order:\n\n\
+         {sections}\n\n\
+         - Facts: key facts, user preferences and corrections\n\
+         - Decisions: decisions and conclusions reached\n\
+         - Open tasks: commitments, plans and action items not done yet\n\
+         - Tool outputs: the tool results still relevant, with the values that matter\n\n\
+         Use short bullet points and write \"- none\" under an empty section. Do not omit \
+         important details. Output only the summary, no preamble."
+    ));
+
+    let transcript = render_transcript(old_messages);
+    let request = match previous {
+        Some(previous) => format!(
+            "Here is the summary of the earlier conversation:\n\n{previous}\n\n\
+             Update it with the messages that followed. Keep what is still true, drop what \
+             was superseded and close the open tasks that were done.\n\n{transcript}"
+        ),
+        None => format!("Summarize this conversation.\n\n{transcript}"),
+    };
+
+    vec![system, ChatMessage::user(request)]
+}
+
+/// Renders messages as plain text, tool calls and results included.
+fn render_transcript(messages: &[genai::chat::ChatMessage]) -> String {
+    use genai::chat::{ChatRole, ContentPart};
+
+    let mut lines = Vec::new();
+    for message in messages {
+        let role = match message.role {
+            ChatRole::System => "System",
+            ChatRole::User => "User",
+            ChatRole::Assistant => "Assistant",
+            ChatRole::Tool => "Tool",
+        };
+        for part in message.content.parts() {
+            match part {
+                ContentPart::Text(text) => lines.push(format!("{role}: {}", text.trim())),
+                ContentPart::ToolCall(call) => lines.push(format!(
+                    "{role} called {} ({}) with {}",
+                    call.fn_name, call.call_id, call.fn_arguments
+                )),
+                ContentPart::ToolResponse(response) => lines.push(format!(
+                    "Tool result ({}): {}",
+                    response.call_id,
+                    response.content.trim()
+                )),
+                ContentPart::Binary(binary) => {
+                    lines.push(format!("{role} attached a {} file", binary.content_type))
+                }
+                ContentPart::ThoughtSignature(_) => {}
+            }
+        }
+    }
+    lines.join("\n\n")
+}
+
+/// Appends the sections the LLM left out, so every summary has the same shape.
+fn normalize_summary(summary: &str) -> String {
+    let mut summary = summary.trim().to_string();
+    for section in SUMMARY_SECTIONS {
+        let heading = format!("## {section}");
+        if !summary.lines().any(|line| line.trim() == heading) {
+            summary.push_str(&format!("\n\n{heading}\n- none"));
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use genai::chat::{ChatMessage, ToolCall};
+
+    #[test]
+    fn heuristic_ignores_json_punctuation() {
+        let message = ChatMessage::from(vec![ToolCall {
+            call_id: String::from("call_1"),
+            fn_name: String::from("weather_forecast"),
+            fn_arguments: serde_json::json!({ "mode": "current" }),
+            thought_signatures: None,
+        }]);
+        let serialized = serde_json::to_string(&message.content).expect("serializable");
+        let counted = HeuristicTokenCounter.count_message(&message);
+        assert!(counted < serialized.len() / 4, "{counted} tokens");
+    }
+
+    fn tool_call(call_id: &str) -> ChatMessage {
+        ChatMessage::from(vec![ToolCall {
+            call_id: call_id.to_string(),
+            fn_name: String::from("weather_forecast"),
+            fn_arguments: serde_json::json!({ "mode": "current" }),
+            thought_signatures: None,
+        }])
+    }
+
+    #[test]
+    fn split_keeps_tool_responses_with_their_call() {
+        let messages = vec![
+            ChatMessage::user("weather in two places?"),
+            tool_call("call_1"),
+            ChatMessage::from(genai::chat::ToolResponse::new("call_1", "72°F")),
+            ChatMessage::from(genai::chat::ToolResponse::new("call_2", "65°F")),
+            ChatMessage::assistant("72°F and 65°F"),
+        ];
+        // Keeping 3 would start on the second tool response
+        assert_eq!(split_point(&messages, 3), 1);
+        assert_eq!(split_point(&messages, 1), 4);
+        assert_eq!(split_point(&messages, 10), 0);
+    }
+
+    #[test]
+    fn previous_summary_is_found_and_updated() {
+        let summary = format!("{SUMMARY_HEADER}12 messages]\n\n## Facts\n- lives in Brooklyn");
+        let messages = vec![ChatMessage::system(summary), ChatMessage::user("hi")];
+        let (count, previous) = previous_summary(&messages).expect("summary is found");
+        assert_eq!(count, 12);
+        assert_eq!(previous, "## Facts\n- lives in Brooklyn");
+
+        let prompt = summary_prompt(Some(previous), &messages[1..]);
+        let request = prompt[1].content.first_text().expect("text prompt");
+        assert!(request.contains("- lives in Brooklyn"));
+        assert!(request.contains("User: hi"));
+
+        assert!(previous_summary(&messages[1..]).is_none());
+    }
+
+    #[test]
+    fn transcript_and_summary_keep_their_structure() {
+        let transcript = render_transcript(&[
+            tool_call("call_1"),
+            ChatMessage::from(genai::chat::ToolResponse::new("call_1", "72°F")),
+        ]);
+        assert!(transcript.contains(r#"weather_forecast (call_1) with {"mode":"current"}"#));
+        assert!(transcript.contains("Tool result (call_1): 72°F"));
+
+        let summary = normalize_summary("## Facts\n- likes tea\n\n## Decisions\n- none");
+        assert!(summary.starts_with("## Facts\n- likes tea"));
+        assert!(summary.ends_with("## Open tasks\n- none\n\n## Tool outputs\n- none"));
+    }
+
+    #[test]
+    fn bpe_counts_real_tokens() {
+        let counter = BpeTokenCounter::o200k();
+        assert_eq!(counter.count_text("hello world"), 2);
+        assert_eq!(
+            counter.count_message(&ChatMessage::user("hello world")),
+            2 + MESSAGE_OVERHEAD_TOKENS
+        );
+        assert!(BpeTokenCounter::for_model("gpt-4o").is_ok());
+    }
+
+    #[test]
+    fn budget_follows_the_token_counter() {
+        let messages: Vec<ChatMessage> = (0..10)
+            .map(|i| ChatMessage::user(format!("message number {i}")))
+            .collect();
+        let config = CompactionConfig {
+            preserve_recent: 2,
+            ..CompactionConfig::for_context_window(100, 40)
+        }
+        .with_token_counter(BpeTokenCounter::o200k());
+        assert_eq!(config.token_budget, 60);
+        // 10 messages of 4 tokens ("message", " number", " ", digit) plus the overhead
+        assert_eq!(
+            estimate_total_tokens(&messages, config.token_counter.as_ref()),
+            80
+        );
+        assert!(should_compact(&messages, &config));
+    }
 }
@@ -0,0 +1,268 @@
+use crate::backend::{ChatEventStream, LlmBackend};
+
+/// What an [`AgentHook`] decides about a tool call before it runs.
+#[derive(Debug, Clone)]
+pub enum ToolHookDecision {
+    /// Let the call run, with the arguments the hook left in it.
+    Continue,
+    /// Do not run the tool. The reason is sent back to the LLM as the tool error.
+    Block { reason: String },
+}
+
+/// Middleware of the agent loop, listed in [`crate::AgentLoopConfig::hooks`].
+///
+/// Hooks are called in the order of the list, each one seeing what the previous ones changed.
+/// Every method does nothing by default. An error returned by a hook stops the loop, so a
+/// redaction hook that cannot do its job keeps the request from leaving the machine.
+#[async_trait::async_trait]
+pub trait AgentHook: Send + Sync {
+    /// Called before every LLM request is sent: the assistant turns, their retries and
+    /// fallbacks, and the compaction summaries. Changes only apply to the request, the
+    /// conversation history keeps the original messages.
+    async fn before_llm_call(
+        &self,
+        _model: &str,
+        _request: &mut genai::chat::ChatRequest,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Called with the assistant message of a turn, before it is added to the conversation
+    /// and its tool calls run.
+    async fn after_llm_call(&self, _message: &mut genai::chat::ChatMessage) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Called before a tool call runs, and before its approval, so the user approves the call
+    /// as the hooks left it. The hook can change the arguments or block the call.
+    async fn before_tool(
+        &self,
+        _tool_call: &mut genai::chat::ToolCall,
+    ) -> anyhow::Result<ToolHookDecision> {
+        Ok(ToolHookDecision::Continue)
+    }
+
+    /// Called with the result of a tool call before it is sent back to the LLM.
+    async fn after_tool(
+        &self,
+        _tool_call: &genai::chat::ToolCall,
+        _content: &mut String,
+        _is_error: bool,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Called after the conversation was compacted, with the messages before compaction and
+    /// the ones replacing them.
+    async fn on_compaction(
+        &self,
+        _original: &[genai::chat::ChatMessage],
+        _compacted: &mut Vec<genai::chat::ChatMessage>,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Runs the `before_tool` hooks in order. The first block wins, the hooks after it are not
+/// called.
+pub(crate) async fn before_tool(
+    hooks: &[std::sync::Arc<dyn AgentHook>],
+    tool_call: &mut genai::chat::ToolCall,
+) -> anyhow::Result<ToolHookDecision> {
+    for hook in hooks {
+        if let ToolHookDecision::Block { reason } = hook.before_tool(tool_call).await? {
+            return Ok(ToolHookDecision::Block { reason });
+        }
+    }
+    Ok(ToolHookDecision::Continue)
+}
+
+/// An [`LlmBackend`] passing every request through the `before_llm_call` hooks.
+pub(crate) struct HookedBackend<'a> {
+    pub inner: &'a dyn LlmBackend,
+    pub hooks: &'a [std::sync::Arc<dyn AgentHook>],
+}
+
+impl HookedBackend<'_> {
+    async fn prepare(
+        &self,
+        model: &str,
+        mut request: genai::chat::ChatRequest,
+    ) -> anyhow::Result<genai::chat::ChatRequest> {
+        for hook in self.hooks {
+            hook.before_llm_call(model, &mut request).await?;
+        }
+        Ok(request)
+    }
+}
+
+#[async_trait::async_trait]
+impl LlmBackend for HookedBackend<'_> {
+    async fn exec_chat_stream(
+        &self,
+        model: &str,
+        request: genai::chat::ChatRequest,
+        options: Option<&genai::chat::ChatOptions>,
+    ) -> anyhow::Result<ChatEventStream> {
+        let request = self.prepare(model, request).await?;
+        self.inner.exec_chat_stream(model, request, options).await
+    }
+
+    async fn exec_chat(
+        &self,
+        model: &str,
+        request: genai::chat::ChatRequest,
+        options: Option<&genai::chat::ChatOptions>,
+    ) -> anyhow::Result<genai::chat::ChatResponse> {
+        let request = self.prepare(model, request).await?;
+        self.inner.exec_chat(model, request, options).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{EchoTool, run_loop};
+    use crate::{AgentLoopConfig, ScriptedBackend, ScriptedResponse};
+
+    /// Redacts an address from the requests, blocks echoes of "stop" and tags the results.
+    struct TestHook;
+
+    #[async_trait::async_trait]
+    impl AgentHook for TestHook {
+        async fn before_llm_call(
+            &self,
+            _model: &str,
+            request: &mut genai::chat::ChatRequest,
+        ) -> anyhow::Result<()> {
+            for message in &mut request.messages {
+                if let Some(text) = message.content.first_text()
+                    && text.contains("jane@example.com")
+                {
+                    *message =
+                        genai::chat::ChatMessage::user(text.replace("jane@example.com", "[email]"));
+                }
+            }
+            Ok(())
+        }
+
+        async fn before_tool(
+            &self,
+            tool_call: &mut genai::chat::ToolCall,
+        ) -> anyhow::Result<ToolHookDecision> {
+            Ok(match tool_call.fn_arguments["text"].as_str() {
+                Some("stop") => ToolHookDecision::Block {
+                    reason: String::from("stop is not echoed"),
+                },
+                Some("jane@example.com") => {
+                    tool_call.fn_arguments = serde_json::json!({ "text": "[email]" });
+                    ToolHookDecision::Continue
+                }
+                _ => ToolHookDecision::Continue,
+            })
+        }
+
+        async fn after_tool(
+            &self,
+            _tool_call: &genai::chat::ToolCall,
+            content: &mut String,
+            _is_error: bool,
+        ) -> anyhow::Result<()> {
+            content.push_str(" (checked)");
+            Ok(())
+        }
+    }
+
+    fn echo_call(call_id: &str, text: &str) -> genai::chat::ToolCall {
+        genai::chat::ToolCall {
+            call_id: call_id.to_string(),
+            fn_name: String::from("echo"),
+            fn_arguments: serde_json::json!({ "text": text }),
+            thought_signatures: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn hooks_rewrite_requests_block_calls_and_annotate_results() {
+        let backend = ScriptedBackend::new([
+            ScriptedResponse::tool_calls(vec![
+                echo_call("call_1", "go"),
+                echo_call("call_2", "stop"),
+            ]),
+            ScriptedResponse::text("Done."),
+        ]);
+        let config = AgentLoopConfig {
+            tools: vec![Box::new(EchoTool {
+                read_only: true,
+                ..EchoTool::new("echo")
+            })],
+            hooks: vec![std::sync::Arc::new(TestHook)],
+            ..Default::default()
+        };
+        let messages = run_loop(
+            &backend,
+            &config,
+            vec![genai::chat::ChatMessage::user("Mail jane@example.com")],
+        )
+        .await
+        .0
+        .expect("loop completes");
+
+        // The history keeps the address, the requests do not
+        assert_eq!(
+            messages[0].content.first_text(),
+            Some("Mail jane@example.com")
+        );
+        for request in backend.requests() {
+            assert_eq!(
+                request.request.messages[0].content.first_text(),
+                Some("Mail [email]")
+            );
+        }
+
+        let results: Vec<String> = messages
+            .iter()
+            .flat_map(|m| m.content.tool_responses())
+            .map(|response| response.content.clone())
+            .collect();
+        assert_eq!(
+            results,
+            [
+                r#"{"text":"go"} (checked)"#,
+                "Tool call blocked: stop is not echoed"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn approval_is_asked_for_the_call_the_hooks_rewrote() {
+        let backend = ScriptedBackend::new([
+            ScriptedResponse::tool_calls(vec![echo_call("call_1", "jane@example.com")]),
+            ScriptedResponse::text("Done."),
+        ]);
+        let approved = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = approved.clone();
+        let config = AgentLoopConfig {
+            tools: vec![Box::new(EchoTool::new("echo"))],
+            hooks: vec![std::sync::Arc::new(TestHook)],
+            approval: Some(Box::new(move |request| {
+                if let Ok(mut seen) = seen.lock() {
+                    seen.push(request.arguments);
+                }
+                Box::pin(async { crate::ToolApproval::Approve })
+            })),
+            ..Default::default()
+        };
+        run_loop(
+            &backend,
+            &config,
+            vec![genai::chat::ChatMessage::user("Echo my address")],
+        )
+        .await
+        .0
+        .expect("loop completes");
+
+        let approved = approved.lock().expect("lock").clone();
+        assert_eq!(approved, [serde_json::json!({ "text": "[email]" })]);
+    }
+}
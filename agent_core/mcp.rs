@@ -0,0 +1,573 @@
+use crate::agent_loop::{AgentEvent, AgentTool};
+use rmcp::ServiceExt;
+use std::sync::Arc;
+
+type McpService = rmcp::service::RunningService<rmcp::RoleClient, ()>;
+
+/// Longest tool name the model providers accept.
+const MAX_TOOL_NAME_LEN: usize = 64;
+
+/// A connection to an MCP server, over stdio when the server has a `command`, over streamable
+/// HTTP when it has a `url`.
+///
+/// The server's tools are exposed through [`McpConnection::tools`] as regular [`AgentTool`]s.
+/// They keep the connection open for as long as they live. What a stdio server writes on its
+/// stderr is logged at the info level.
+///
+/// Connecting waits for the server to answer, callers bound it with a timeout.
+pub struct McpConnection {
+    name: String,
+    service: Arc<McpService>,
+}
+
+impl McpConnection {
+    pub async fn connect(server: &config::McpServer) -> anyhow::Result<Self> {
+        let service = match (&server.command, &server.url) {
+            (Some(command), None) => {
+                let mut command = tokio::process::Command::new(command);
+                command.args(&server.args).envs(&server.env);
+                let (transport, stderr) = rmcp::transport::TokioChildProcess::builder(command)
+                    .stderr(std::process::Stdio::piped())
+                    .spawn()?;
+                if let Some(stderr) = stderr {
+                    tokio::spawn(log_stderr(server.name.clone(), stderr));
+                }
+                ().serve(transport).await?
+            }
+            (None, Some(url)) => {
+                let transport =
+                    rmcp::transport::StreamableHttpClientTransport::from_uri(url.as_str());
+                ().serve(transport).await?
+            }
+            _ => anyhow::bail!(
+                "MCP server '{}' needs either a command or a url",
+                server.name
+            ),
+        };
+        Ok(Self::new(&server.name, service))
+    }
+
+    fn new(name: &str, service: McpService) -> Self {
+        Self {
+            name: name.to_string(),
+            service: Arc::new(service),
+        }
+    }
+
+    /// Every tool of the server, named `<server>_<tool>` so tools of different servers do not
+    /// collide. Tools annotated with `readOnlyHint` are read-only, the others go through the
+    /// approval of mutating tools.
+    ///
+    /// A tool whose name is longer than 64 characters, or only differs from an earlier tool by
+    /// the characters replaced in its name (`add.note` and `add_note`), is skipped with a warning.
+    pub async fn tools(&self) -> anyhow::Result<Vec<Box<dyn AgentTool>>> {
+        let mut tools: Vec<McpTool> = Vec::new();
+        for tool in self.service.list_all_tools().await? {
+            let name = tool_name(&self.name, &tool.name);
+            if name.len() > MAX_TOOL_NAME_LEN {
+                log::warn!(
+                    "Skipping MCP tool '{}' of '{}': '{name}' is longer than {MAX_TOOL_NAME_LEN} characters",
+                    tool.name,
+                    self.name
+                );
+            } else if let Some(taken) = tools.iter().find(|taken| taken.name == name) {
+                log::warn!(
+                    "Skipping MCP tool '{}' of '{}': its name '{name}' is taken by '{}'",
+                    tool.name,
+                    self.name,
+                    taken.tool.name
+                );
+            } else {
+                tools.push(McpTool {
+                    name,
+                    tool,
+                    service: self.service.clone(),
+                });
+            }
+        }
+        Ok(tools
+            .into_iter()
+            .map(|tool| Box::new(tool) as Box<dyn AgentTool>)
+            .collect())
+    }
+}
+
+/// Logs the lines a server writes on its stderr until it exits.
+async fn log_stderr(server: String, stderr: tokio::process::ChildStderr) {
+    use tokio::io::AsyncBufReadExt;
+
+    let mut lines = tokio::io::BufReader::new(stderr).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        log::info!("MCP server '{server}': {line}");
+    }
+}
+
+/// A tool of an MCP server.
+struct McpTool {
+    name: String,
+    tool: rmcp::model::Tool,
+    service: Arc<McpService>,
+}
+
+#[async_trait::async_trait]
+impl AgentTool for McpTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn definition(&self) -> genai::chat::Tool {
+        let mut definition = genai::chat::Tool::new(self.name.as_str())
+            .with_schema(serde_json::Value::Object((*self.tool.input_schema).clone()));
+        if let Some(description) = &self.tool.description {
+            definition = definition.with_description(description.as_ref());
+        }
+        definition
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.tool
+            .annotations
+            .as_ref()
+            .and_then(|annotations| annotations.read_only_hint)
+            .unwrap_or(false)
+    }
+
+    /// Calls the tool on the server. When `cancel` fires first, the server is told to drop the
+    /// request with a cancellation notification.
+    async fn execute(
+        &self,
+        _call_id: &str,
+        arguments: serde_json::Value,
+        _event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+        cancel: &tokio_util::sync::CancellationToken,
+    ) -> anyhow::Result<String> {
+        let mut params = rmcp::model::CallToolRequestParams::new(self.tool.name.clone());
+        match arguments {
+            serde_json::Value::Object(arguments) => params = params.with_arguments(arguments),
+            serde_json::Value::Null => {}
+            other => anyhow::bail!("Tool arguments must be an object, got {other}"),
+        }
+
+        let request = self
+            .service
+            .send_cancellable_request(
+                rmcp::model::ClientRequest::CallToolRequest(rmcp::model::CallToolRequest::new(
+                    params,
+                )),
+                rmcp::service::PeerRequestOptions::no_options(),
+            )
+            .await?;
+        let request_id = request.id.clone();
+        let response = tokio::select! {
+            response = request.await_response() => response?,
+            () = cancel.cancelled() => {
+                let notification = rmcp::model::CancelledNotification::new(
+                    rmcp::model::CancelledNotificationParam::new(
+                        Some(request_id),
+                        Some(String::from("The agent cancelled the tool call")),
+                    ),
+                );
+                if let Err(e) = self.service.send_notification(notification.into()).await {
+                    log::warn!("Could not cancel the call of MCP tool '{}': {e}", self.name);
+                }
+                anyhow::bail!("Tool call cancelled");
+            }
+        };
+        let rmcp::model::ServerResult::CallToolResult(result) = response else {
+            anyhow::bail!("MCP tool '{}' did not answer with a tool result", self.name);
+        };
+        let output = result_text(&result);
+        if result.is_error == Some(true) {
+            anyhow::bail!(output);
+        }
+        Ok(output)
+    }
+}
+
+/// Tool names sent to the LLM may only contain letters, digits, `_` and `-`.
+fn tool_name(server: &str, tool: &str) -> String {
+    format!("{server}_{tool}")
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Text blocks of the result joined together. Other blocks, and structured content when there
+/// is no text, are sent as JSON.
+fn result_text(result: &rmcp::model::CallToolResult) -> String {
+    let blocks: Vec<String> = result
+        .content
+        .iter()
+        .map(|block| match block.as_text() {
+            Some(text) => text.text.clone(),
+            None => serde_json::to_string(block).unwrap_or_default(),
+        })
+        .collect();
+    match (&result.structured_content, blocks.is_empty()) {
+        (Some(structured), true) => structured.to_string(),
+        _ => blocks.join("\n"),
+    }
+}
+
+/// Serves tools to MCP clients over stdio, the reverse of [`McpConnection`]: other agents and
+/// editors call the same [`AgentTool`] adapters the agent loop uses.
+///
+/// Arguments are checked against the tool schema before the tool runs, like in the agent loop.
+/// Tools run under their own [`AgentTool::timeout`], if any.
+pub struct McpToolServer {
+    name: String,
+    version: String,
+    tools: Vec<Box<dyn AgentTool>>,
+}
+
+impl McpToolServer {
+    pub fn new(name: &str, version: &str, tools: Vec<Box<dyn AgentTool>>) -> Self {
+        Self {
+            name: name.to_string(),
+            version: version.to_string(),
+            tools,
+        }
+    }
+
+    /// Serves the tools on stdin and stdout until the client disconnects.
+    pub async fn serve_stdio(self) -> anyhow::Result<()> {
+        let server = self.serve(rmcp::transport::stdio()).await?;
+        server.waiting().await?;
+        Ok(())
+    }
+
+    async fn call(
+        &self,
+        name: &str,
+        arguments: Option<rmcp::model::JsonObject>,
+        cancel: &tokio_util::sync::CancellationToken,
+    ) -> Result<String, String> {
+        let tool = self
+            .tools
+            .iter()
+            .find(|tool| tool.name() == name)
+            .ok_or_else(|| format!("Tool '{name}' not found"))?;
+        let arguments = serde_json::Value::Object(arguments.unwrap_or_default());
+        crate::tool_schema::validate_arguments(&tool.definition(), &arguments)?;
+
+        // Nobody listens to progress updates, the receiver only keeps the channel open
+        let (event_tx, _event_rx) = tokio::sync::mpsc::unbounded_channel();
+        let call_id = uuid::Uuid::new_v4().to_string();
+        let execution = tool.execute(&call_id, arguments, &event_tx, cancel);
+        let result = match tool.timeout() {
+            Some(timeout) => match tokio::time::timeout(timeout, execution).await {
+                Ok(result) => result,
+                Err(_) => {
+                    return Err(format!(
+                        "Tool '{name}' did not finish within {} seconds",
+                        timeout.as_secs_f64()
+                    ));
+                }
+            },
+            None => execution.await,
+        };
+        result.map_err(|e| e.to_string())
+    }
+}
+
+impl rmcp::ServerHandler for McpToolServer {
+    fn get_info(&self) -> rmcp::model::ServerConfig {
+        rmcp::model::ServerConfig::new(
+            rmcp::model::ServerCapabilities::builder()
+                .enable_tools()
+                .build(),
+        )
+        .with_server_info(rmcp::model::Implementation::new(
+            self.name.as_str(),
+            self.version.as_str(),
+        ))
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<rmcp::model::PaginatedRequestParams>,
+        _context: rmcp::service::RequestContext<rmcp::RoleServer>,
+    ) -> Result<rmcp::model::ListToolsResult, rmcp::ErrorData> {
+        let tools = self
+            .tools
+            .iter()
+            .map(|tool| {
+                let definition = tool.definition();
+                let schema = match definition.schema {
+                    Some(serde_json::Value::Object(schema)) => schema,
+                    _ => rmcp::model::JsonObject::from_iter([(
+                        String::from("type"),
+                        serde_json::Value::from("object"),
+                    )]),
+                };
+                rmcp::model::Tool::new(
+                    definition.name.to_string(),
+                    definition.description.unwrap_or_default(),
+                    schema,
+                )
+                .with_annotations(
+                    rmcp::model::ToolAnnotations::new().read_only(tool.is_read_only()),
+                )
+            })
+            .collect();
+        Ok(rmcp::model::ListToolsResult::with_all_items(tools))
+    }
+
+    async fn call_tool(
+        &self,
+        request: rmcp::model::CallToolRequestParams,
+        context: rmcp::service::RequestContext<rmcp::RoleServer>,
+    ) -> Result<rmcp::model::CallToolResponse, rmcp::ErrorData> {
+        let result = match self
+            .call(&request.name, request.arguments, &context.ct)
+            .await
+        {
+            Ok(output) => {
+                rmcp::model::CallToolResult::success(vec![rmcp::model::ContentBlock::text(output)])
+            }
+            Err(error) => {
+                rmcp::model::CallToolResult::error(vec![rmcp::model::ContentBlock::text(error)])
+            }
+        };
+        Ok(result.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TypedTool;
+
+    #[derive(serde::Deserialize, schemars::JsonSchema)]
+    struct NoteArgs {
+        text: String,
+    }
+
+    /// Read-only notes search.
+    struct SearchNotes;
+
+    #[async_trait::async_trait]
+    impl TypedTool for SearchNotes {
+        type Args = NoteArgs;
+
+        fn name(&self) -> &str {
+            "search"
+        }
+
+        fn description(&self) -> &str {
+            "Search the notes"
+        }
+
+        fn is_read_only(&self) -> bool {
+            true
+        }
+
+        async fn run(
+            &self,
+            _call_id: &str,
+            args: NoteArgs,
+            _event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+            _cancel: &tokio_util::sync::CancellationToken,
+        ) -> anyhow::Result<String> {
+            Ok(format!("found {}", args.text))
+        }
+    }
+
+    /// Mutating tool that always fails.
+    struct AddNote;
+
+    #[async_trait::async_trait]
+    impl TypedTool for AddNote {
+        type Args = NoteArgs;
+
+        fn name(&self) -> &str {
+            "add.note"
+        }
+
+        fn description(&self) -> &str {
+            "Add a note"
+        }
+
+        async fn run(
+            &self,
+            _call_id: &str,
+            _args: NoteArgs,
+            _event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+            _cancel: &tokio_util::sync::CancellationToken,
+        ) -> anyhow::Result<String> {
+            anyhow::bail!("notes are read-only")
+        }
+    }
+
+    /// Tool that only has a name.
+    struct Named(String);
+
+    #[async_trait::async_trait]
+    impl TypedTool for Named {
+        type Args = serde_json::Map<String, serde_json::Value>;
+
+        fn name(&self) -> &str {
+            &self.0
+        }
+
+        fn description(&self) -> &str {
+            "Does nothing"
+        }
+
+        async fn run(
+            &self,
+            _call_id: &str,
+            _args: Self::Args,
+            _event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+            _cancel: &tokio_util::sync::CancellationToken,
+        ) -> anyhow::Result<String> {
+            Ok(String::new())
+        }
+    }
+
+    /// Tool that runs until it is cancelled, then reports the cancellation.
+    struct WaitForCancel {
+        cancelled: tokio::sync::mpsc::UnboundedSender<()>,
+    }
+
+    #[async_trait::async_trait]
+    impl TypedTool for WaitForCancel {
+        type Args = serde_json::Map<String, serde_json::Value>;
+
+        fn name(&self) -> &str {
+            "wait"
+        }
+
+        fn description(&self) -> &str {
+            "Wait until cancelled"
+        }
+
+        async fn run(
+            &self,
+            _call_id: &str,
+            _args: Self::Args,
+            _event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+            cancel: &tokio_util::sync::CancellationToken,
+        ) -> anyhow::Result<String> {
+            cancel.cancelled().await;
+            let _ = self.cancelled.send(());
+            anyhow::bail!("cancelled")
+        }
+    }
+
+    /// Serves the notes tools and connects to them over an in-memory pipe, framed like stdio.
+    async fn connect_notes_server() -> McpConnection {
+        connect_server(vec![Box::new(SearchNotes), Box::new(AddNote)]).await
+    }
+
+    async fn connect_server(tools: Vec<Box<dyn AgentTool>>) -> McpConnection {
+        let server = McpToolServer::new("notes", "0.1.0", tools);
+        let (server_io, client_io) = tokio::io::duplex(4096);
+        tokio::spawn(async move {
+            if let Ok(server) = server.serve(server_io).await {
+                let _ = server.waiting().await;
+            }
+        });
+        let service = ().serve(client_io).await.expect("server initializes");
+        McpConnection::new("notes", service)
+    }
+
+    #[tokio::test]
+    async fn tools_round_trip_through_mcp() {
+        let connection = connect_notes_server().await;
+        let tools = connection.tools().await.expect("tools are listed");
+        let names: Vec<&str> = tools.iter().map(|tool| tool.name()).collect();
+        assert_eq!(names, ["notes_search", "notes_add_note"]);
+        assert!(tools[0].is_read_only());
+        assert!(!tools[1].is_read_only());
+        assert_eq!(
+            tools[0].definition().schema.expect("schema is kept")["required"][0],
+            "text"
+        );
+
+        let (event_tx, _event_rx) = tokio::sync::mpsc::unbounded_channel();
+        let cancel = tokio_util::sync::CancellationToken::new();
+        let found = tools[0]
+            .execute(
+                "call_1",
+                serde_json::json!({ "text": "milk" }),
+                &event_tx,
+                &cancel,
+            )
+            .await
+            .expect("search succeeds");
+        assert_eq!(found, "found milk");
+
+        let error = tools[1]
+            .execute(
+                "call_2",
+                serde_json::json!({ "text": "milk" }),
+                &event_tx,
+                &cancel,
+            )
+            .await
+            .expect_err("tool errors are errors");
+        assert_eq!(error.to_string(), "notes are read-only");
+
+        let invalid = tools[0]
+            .execute("call_3", serde_json::json!({}), &event_tx, &cancel)
+            .await
+            .expect_err("arguments are checked by the server");
+        assert!(invalid.to_string().contains("invalid_arguments"));
+    }
+
+    #[tokio::test]
+    async fn colliding_and_long_tool_names_are_skipped() {
+        let connection = connect_server(vec![
+            Box::new(AddNote),
+            Box::new(Named(String::from("add_note"))),
+            Box::new(Named("n".repeat(58))),
+            Box::new(Named("o".repeat(59))),
+        ])
+        .await;
+        let tools = connection.tools().await.expect("tools are listed");
+        let names: Vec<&str> = tools.iter().map(|tool| tool.name()).collect();
+        assert_eq!(
+            names,
+            [
+                String::from("notes_add_note"),
+                format!("notes_{}", "n".repeat(58))
+            ]
+        );
+        assert_eq!(
+            tools[0].definition().description.as_deref(),
+            Some("Add a note")
+        );
+    }
+
+    #[tokio::test]
+    async fn cancelling_a_call_cancels_it_on_the_server() {
+        let (cancelled_tx, mut cancelled_rx) = tokio::sync::mpsc::unbounded_channel();
+        let connection = connect_server(vec![Box::new(WaitForCancel {
+            cancelled: cancelled_tx,
+        })])
+        .await;
+        let tools = connection.tools().await.expect("tools are listed");
+
+        let (event_tx, _event_rx) = tokio::sync::mpsc::unbounded_channel();
+        let cancel = tokio_util::sync::CancellationToken::new();
+        let call = tools[0].execute("call_1", serde_json::json!({}), &event_tx, &cancel);
+        let cancel_soon = async {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            cancel.cancel();
+        };
+        let (result, ()) = tokio::join!(call, cancel_soon);
+        assert_eq!(
+            result.expect_err("the call is cancelled").to_string(),
+            "Tool call cancelled"
+        );
+
+        tokio::time::timeout(std::time::Duration::from_secs(5), cancelled_rx.recv())
+            .await
+            .expect("the server sees the cancellation")
+            .expect("the server tool reports it");
+    }
+}
@@ -0,0 +1,187 @@
+use std::time::Duration;
+
+/// Exponential backoff applied when retrying transient failures.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt. `0` disables retrying.
+    pub max_retries: usize,
+
+    /// Delay before the first retry.
+    pub initial_backoff: Duration,
+
+    /// Upper bound for the delay between two attempts.
+    pub max_backoff: Duration,
+
+    /// Factor applied to the delay after every retry.
+    pub multiplier: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    /// Delay to wait before retry number `retry` (0-indexed).
+    pub fn backoff(&self, retry: usize) -> Duration {
+        let factor = self.multiplier.powi(retry.min(i32::MAX as usize) as i32);
+        let backoff = self.initial_backoff.as_secs_f64() * factor;
+        if backoff.is_nan() || backoff >= self.max_backoff.as_secs_f64() {
+            return self.max_backoff;
+        }
+        Duration::from_secs_f64(backoff.max(0.0))
+    }
+}
+
+/// Marks a tool error as transient (network hiccup, rate limit, flaky scrape).
+///
+/// The agent loop retries tools that fail with this error according to the configured
+/// [`RetryPolicy`]. Any other error is reported to the model straight away.
+#[derive(Debug)]
+pub struct TransientToolError(anyhow::Error);
+
+impl TransientToolError {
+    pub fn new(error: impl Into<anyhow::Error>) -> Self {
+        Self(error.into())
+    }
+}
+
+impl std::fmt::Display for TransientToolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#}", self.0)
+    }
+}
+
+impl std::error::Error for TransientToolError {}
+
+/// Whether `error`, or any error it wraps, is a [`TransientToolError`].
+pub(crate) fn is_transient(error: &anyhow::Error) -> bool {
+    error.chain().any(|e| e.is::<TransientToolError>())
+}
+
+/// The LLM stream stopped before its `End` event, usually because the connection dropped.
+#[derive(Debug)]
+pub(crate) struct IncompleteStream;
+
+impl std::fmt::Display for IncompleteStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Stream ended without End event")
+    }
+}
+
+impl std::error::Error for IncompleteStream {}
+
+/// Whether an LLM request failed for a reason that may go away on its own:
+/// rate limits, server errors, network failures and dropped streams.
+pub(crate) fn is_transient_llm_error(error: &anyhow::Error) -> bool {
+    if error.is::<IncompleteStream>() {
+        return true;
+    }
+    if let Some(error) = error.downcast_ref::<crate::cassette::ReplayedLlmError>() {
+        return error.transient;
+    }
+    let Some(error) = error.downcast_ref::<genai::Error>() else {
+        return false;
+    };
+    match error {
+        genai::Error::WebAdapterCall { webc_error, .. }
+        | genai::Error::WebModelCall { webc_error, .. } => match webc_error {
+            genai::webc::Error::ResponseFailedStatus { status, .. } => {
+                is_transient_status(status.as_u16())
+            }
+            genai::webc::Error::Reqwest(_) => true,
+            _ => false,
+        },
+        genai::Error::HttpError { status, .. } => is_transient_status(status.as_u16()),
+        genai::Error::WebStream { .. } => true,
+        genai::Error::ChatResponse { body, .. } => {
+            let body = body.to_string();
+            ["overloaded", "rate_limit", "server_error"]
+                .iter()
+                .any(|kind| body.contains(kind))
+        }
+        _ => false,
+    }
+}
+
+fn is_transient_status(status: u16) -> bool {
+    matches!(status, 408 | 429) || status >= 500
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(350),
+            multiplier: 2.0,
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(1), Duration::from_millis(200));
+        assert_eq!(policy.backoff(2), Duration::from_millis(350));
+        assert_eq!(policy.backoff(30), Duration::from_millis(350));
+    }
+
+    #[test]
+    fn transient_llm_errors_are_recognised() {
+        let model_iden = genai::ModelIden::new(genai::adapter::AdapterKind::OpenAI, "gpt-4o");
+        assert!(is_transient_llm_error(&anyhow::Error::new(
+            IncompleteStream
+        )));
+        assert!(is_transient_llm_error(&anyhow::Error::new(
+            genai::Error::WebStream {
+                model_iden: model_iden.clone(),
+                cause: String::from("connection reset"),
+                error: "connection reset".into(),
+            }
+        )));
+        assert!(is_transient_llm_error(&anyhow::Error::new(
+            genai::Error::ChatResponse {
+                model_iden: model_iden.clone(),
+                body: serde_json::json!({ "type": "overloaded_error" }),
+            }
+        )));
+        assert!(!is_transient_llm_error(&anyhow::Error::new(
+            genai::Error::RequiresApiKey { model_iden }
+        )));
+        assert!(!is_transient_llm_error(&anyhow::anyhow!(
+            "Agent loop cancelled"
+        )));
+
+        assert!(is_transient_status(429));
+        assert!(is_transient_status(503));
+        assert!(!is_transient_status(400));
+        assert!(!is_transient_status(401));
+    }
+
+    #[test]
+    fn transient_errors_are_found_behind_context() {
+        let error = anyhow::Error::new(TransientToolError::new(anyhow::anyhow!("503")))
+            .context("while scraping");
+        assert!(is_transient(&error));
+        assert!(!is_transient(
+            &Err::<(), _>(anyhow::anyhow!("404"))
+                .context("while scraping")
+                .expect_err("is an error")
+        ));
+    }
+}
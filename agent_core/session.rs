@@ -1,15 +1,11 @@
+use crate::session_store::SessionStore;
+use crate::usage::TokenUsage;
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
-
-fn sessions_dir() -> Result<PathBuf> {
-    let storage = config::application_storage(true)?.join("sessions");
-    if !storage.exists() {
-        std::fs::create_dir_all(storage.clone())?;
-    }
-    Ok(storage)
-}
+use std::sync::Arc;
 
 /// On-disk representation of a session. Serialized as JSON.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,70 +15,228 @@ pub struct SessionFile {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub messages: Vec<genai::chat::ChatMessage>,
+    /// ID of the session this one was forked from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
+    /// Number of the parent's messages copied into this session when it was forked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forked_at: Option<usize>,
+    /// Tokens consumed by the session, per model.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub usage: BTreeMap<String, TokenUsage>,
+}
+
+impl SessionFile {
+    /// Build a new session file holding the first `at_index` messages of this one.
+    ///
+    /// The fork gets a fresh id and points back to this session through `parent_id`.
+    /// Forking in the middle of a tool call exchange is rejected, since the fork would
+    /// start with tool calls that never get a response.
+    pub fn fork(&self, at_index: usize) -> Result<SessionFile> {
+        anyhow::ensure!(
+            at_index <= self.messages.len(),
+            "Cannot fork at message {at_index}: session {} only has {} messages",
+            self.id,
+            self.messages.len()
+        );
+        anyhow::ensure!(
+            self.messages
+                .get(at_index)
+                .is_none_or(|m| m.role != genai::chat::ChatRole::Tool),
+            "Cannot fork at message {at_index}: it answers a tool call of the previous message"
+        );
+
+        let now = Utc::now();
+        Ok(SessionFile {
+            id: uuid::Uuid::new_v4().to_string(),
+            model: self.model.clone(),
+            created_at: now,
+            updated_at: now,
+            messages: self.messages[..at_index].to_vec(),
+            parent_id: Some(self.id.clone()),
+            forked_at: Some(at_index),
+            usage: BTreeMap::new(),
+        })
+    }
+
+    /// Add the tokens of an LLM response to the session totals.
+    pub fn record_usage(&mut self, model: &str, usage: TokenUsage) {
+        *self.usage.entry(model.to_string()).or_default() += usage;
+    }
+
+    /// Tokens consumed by the session, all models together.
+    pub fn total_usage(&self) -> TokenUsage {
+        self.usage.values().copied().sum()
+    }
+
+    /// First non-empty line of the first user message, used to recognise a session in
+    /// listings.
+    pub fn first_user_line(&self) -> Option<&str> {
+        self.messages
+            .iter()
+            .filter(|m| m.role == genai::chat::ChatRole::User)
+            .find_map(|m| m.content.first_text())
+            .and_then(|text| text.lines().find(|line| !line.trim().is_empty()))
+    }
+
+    pub fn summary(&self) -> SessionSummary {
+        SessionSummary {
+            id: self.id.clone(),
+            model: self.model.clone(),
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            message_count: self.messages.len(),
+            parent_id: self.parent_id.clone(),
+            forked_at: self.forked_at,
+            first_user_line: self.first_user_line().map(str::to_string),
+        }
+    }
+}
+
+/// A session as listed by [`SessionStore::list`], without its messages.
+#[derive(Debug, Clone)]
+pub struct SessionSummary {
+    pub id: String,
+    pub model: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub message_count: usize,
+    pub parent_id: Option<String>,
+    pub forked_at: Option<usize>,
+    /// See [`SessionFile::first_user_line`].
+    pub first_user_line: Option<String>,
+}
+
+/// All sessions forked from `session_id`, directly or through other forks, parents first.
+fn descendants_of(session_id: &str, sessions: &[SessionSummary]) -> Vec<SessionSummary> {
+    let mut descendants: Vec<SessionSummary> = Vec::new();
+    let mut parents = vec![session_id.to_string()];
+    while let Some(parent) = parents.pop() {
+        for child in sessions
+            .iter()
+            .filter(|s| s.parent_id.as_deref() == Some(parent.as_str()))
+        {
+            if descendants.iter().all(|d| d.id != child.id) {
+                parents.push(child.id.clone());
+                descendants.push(child.clone());
+            }
+        }
+    }
+    descendants
 }
 
 #[derive(Debug, Clone)]
 pub struct Session {
     pub file: SessionFile,
-    path: PathBuf,
+    store: Arc<dyn SessionStore>,
+    /// Number of messages in the store, and the last of them as JSON. `None` until the
+    /// session is first saved.
+    saved: Option<(usize, String)>,
+    /// Records appended to the store since the session was last saved whole.
+    appended_records: usize,
 }
 
 impl Session {
-    pub fn new(model: &str) -> Result<Self> {
-        let dir = sessions_dir()?;
-        let id = uuid::Uuid::new_v4();
+    pub fn new(store: Arc<dyn SessionStore>, model: &str) -> Result<Self> {
         let now = Utc::now();
         let mut session = Self {
             file: SessionFile {
-                id: id.to_string(),
+                id: uuid::Uuid::new_v4().to_string(),
                 model: model.to_string(),
                 created_at: now,
                 updated_at: now,
                 messages: vec![],
+                parent_id: None,
+                forked_at: None,
+                usage: BTreeMap::new(),
             },
-            path: dir.join(format!("{}.json", id)),
+            store,
+            saved: None,
+            appended_records: 0,
         };
         session.save()?;
         Ok(session)
     }
 
-    pub fn load(session_id: &str) -> Result<Self> {
-        let dir = sessions_dir()?;
-        let path = dir.join(format!("{session_id}.json"));
-        let reader = std::io::BufReader::new(std::fs::File::open(&path)?);
-        let file: SessionFile = serde_json::from_reader(reader)?;
-        Ok(Self { file, path })
+    /// Create and save a new session holding this session's first `at_index` messages.
+    /// See [`SessionFile::fork`].
+    pub fn fork(&self, at_index: usize) -> Result<Self> {
+        let file = self.file.fork(at_index)?;
+        let mut session = Self {
+            file,
+            store: self.store.clone(),
+            saved: None,
+            appended_records: 0,
+        };
+        session.save()?;
+
+        // Handles of saved tool outputs in the copied messages keep working in the fork
+        let outputs_dir = self.outputs_dir()?;
+        if outputs_dir.exists() {
+            let fork_outputs_dir = session.outputs_dir()?;
+            std::fs::create_dir_all(&fork_outputs_dir)?;
+            for entry in std::fs::read_dir(outputs_dir)? {
+                let entry = entry?;
+                std::fs::copy(entry.path(), fork_outputs_dir.join(entry.file_name()))?;
+            }
+        }
+        Ok(session)
     }
 
+    pub fn load(store: Arc<dyn SessionStore>, session_id: &str) -> Result<Self> {
+        let file = store.load(session_id)?;
+        let saved = Some(saved_state(&file.messages)?);
+        Ok(Self {
+            file,
+            store,
+            saved,
+            appended_records: 0,
+        })
+    }
+
+    /// Save the session to its store. When messages were only added since the last save, the
+    /// store is asked to append them, see [`SessionStore::append`]. After a few hundred appended
+    /// records the session is saved whole, so a log does not grow without end.
     pub fn save(&mut self) -> Result<()> {
         self.file.updated_at = Utc::now();
-        let json = serde_json::to_string(&self.file)?;
-        std::fs::write(&self.path, json)?;
+        let appended_from = match &self.saved {
+            Some((count, last)) if *count <= self.file.messages.len() => {
+                let unchanged = match count.checked_sub(1) {
+                    Some(index) => serde_json::to_string(&self.file.messages[index])? == *last,
+                    None => true,
+                };
+                unchanged.then_some(*count)
+            }
+            _ => None,
+        };
+        match appended_from {
+            Some(from) if self.appended_records < crate::session_log::COMPACT_AFTER_RECORDS => {
+                self.store.append(&self.file, from)?;
+                // The appended messages and the update of the changing fields
+                self.appended_records += self.file.messages.len() - from + 1;
+            }
+            // Compaction rewrote the history, the session was never saved, or its log is long
+            _ => {
+                self.store.save(&self.file)?;
+                self.appended_records = 0;
+            }
+        }
+        self.saved = Some(saved_state(&self.file.messages)?);
         Ok(())
     }
 
-    pub fn list() -> Result<Vec<SessionFile>> {
-        let dir = sessions_dir()?;
-        let mut sessions: Vec<SessionFile> = std::fs::read_dir(&dir)?
-            .filter_map(|entry| entry.ok())
-            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
-            .filter_map(|entry| {
-                let reader = std::io::BufReader::new(std::fs::File::open(entry.path()).ok()?);
-                serde_json::from_reader::<_, SessionFile>(reader).ok()
-            })
-            .collect();
-        sessions.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
-        Ok(sessions)
+    /// List every session forked from `session_id`, including forks of forks.
+    pub fn descendants(store: &dyn SessionStore, session_id: &str) -> Result<Vec<SessionSummary>> {
+        Ok(descendants_of(session_id, &store.list()?))
     }
 
-    pub fn delete(session_id: &str) -> Result<()> {
-        let dir = sessions_dir()?;
-        let path = dir.join(format!("{session_id}.json"));
-        std::fs::remove_file(path)?;
-        Ok(())
+    /// Directory of the large tool outputs saved during this session, see
+    /// [`crate::ToolOutputStore`].
+    pub fn outputs_dir(&self) -> Result<PathBuf> {
+        self.store.outputs_dir(&self.file.id)
     }
 
-    /// Create a persist callback that saves messages to this session's file on each call.
+    /// Create a persist callback that saves messages to this session's store on each call.
     pub fn persist_callback(self) -> (PersistFn, std::sync::Arc<std::sync::Mutex<Session>>) {
         let session = std::sync::Arc::new(std::sync::Mutex::new(self));
         let session_clone = session.clone();
@@ -93,6 +247,153 @@ impl Session {
         });
         (callback, session)
     }
+
+    /// Record the tokens of the `Usage` events of a run in a session shared with its persist
+    /// callback, see [`Session::persist_callback`]. Every event is passed on to the returned
+    /// receiver. Once `events` closes, the session is saved when the run used any tokens.
+    pub fn track_usage(
+        session: std::sync::Arc<std::sync::Mutex<Session>>,
+        mut events: tokio::sync::mpsc::UnboundedReceiver<crate::AgentEvent>,
+    ) -> tokio::sync::mpsc::UnboundedReceiver<crate::AgentEvent> {
+        let (event_tx, event_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut used = false;
+            while let Some(event) = events.recv().await {
+                if let crate::AgentEvent::Usage { model, usage } = &event {
+                    match session.lock() {
+                        Ok(mut session) => {
+                            session.file.record_usage(model, *usage);
+                            used = true;
+                        }
+                        Err(e) => log::warn!("Could not record the token usage: {e}"),
+                    }
+                }
+                // The events are still recorded when nobody reads them
+                let _ = event_tx.send(event);
+            }
+            if used {
+                let saved = session
+                    .lock()
+                    .map_err(|e| anyhow::anyhow!("{e}"))
+                    .and_then(|mut session| session.save());
+                if let Err(e) = saved {
+                    log::warn!("Could not save the token usage of the session: {e}");
+                }
+            }
+        });
+        event_rx
+    }
+}
+
+fn saved_state(messages: &[genai::chat::ChatMessage]) -> Result<(usize, String)> {
+    let last = match messages.last() {
+        Some(message) => serde_json::to_string(message)?,
+        None => String::new(),
+    };
+    Ok((messages.len(), last))
 }
 
 pub type PersistFn = Box<dyn Fn(&[genai::chat::ChatMessage]) -> anyhow::Result<()> + Send + Sync>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::weather_conversation;
+
+    fn session_file(id: &str, parent_id: Option<&str>) -> SessionFile {
+        SessionFile {
+            parent_id: parent_id.map(str::to_string),
+            ..crate::test_support::session_file(id, weather_conversation())
+        }
+    }
+
+    #[test]
+    fn fork_copies_messages_before_index() {
+        let parent = session_file("parent", None);
+        let fork = parent.fork(4).expect("fork before the second prompt");
+        assert_ne!(fork.id, parent.id);
+        assert_eq!(fork.parent_id.as_deref(), Some("parent"));
+        assert_eq!(fork.forked_at, Some(4));
+        assert_eq!(fork.messages.len(), 4);
+        assert_eq!(fork.model, parent.model);
+    }
+
+    #[test]
+    fn fork_rejects_out_of_range_and_tool_responses() {
+        let parent = session_file("parent", None);
+        assert!(parent.fork(6).is_err());
+        assert!(parent.fork(2).is_err(), "index 2 is a tool response");
+        assert!(parent.fork(0).is_ok());
+        assert!(parent.fork(5).is_ok());
+    }
+
+    #[test]
+    fn usage_is_recorded_per_model() {
+        let mut file = session_file("session", None);
+        let usage = TokenUsage {
+            prompt_tokens: 100,
+            completion_tokens: 20,
+            cached_tokens: 50,
+        };
+        file.record_usage("gpt-4o", usage);
+        file.record_usage("gpt-4o", usage);
+        file.record_usage("gpt-4o-mini", usage);
+
+        assert_eq!(file.usage["gpt-4o"].prompt_tokens, 200);
+        assert_eq!(file.total_usage().completion_tokens, 60);
+        assert!(file.fork(0).expect("fork").usage.is_empty());
+    }
+
+    #[tokio::test]
+    async fn usage_events_are_recorded_and_saved() {
+        let dir = crate::test_support::TempDir::new("session-usage");
+        let store: Arc<dyn SessionStore> = Arc::new(crate::JsonSessionStore::new(dir.path()));
+        let session = Session::new(store.clone(), "gpt-4o").expect("session is created");
+        let id = session.file.id.clone();
+        let (_persist, shared) = session.persist_callback();
+
+        let (event_tx, event_rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut events = Session::track_usage(shared, event_rx);
+        let usage = TokenUsage {
+            prompt_tokens: 100,
+            completion_tokens: 20,
+            cached_tokens: 0,
+        };
+        for model in ["gpt-4o", "gpt-4o-mini"] {
+            event_tx
+                .send(crate::AgentEvent::Usage {
+                    model: model.to_string(),
+                    usage,
+                })
+                .expect("event is sent");
+        }
+        drop(event_tx);
+
+        let mut forwarded = 0;
+        while events.recv().await.is_some() {
+            forwarded += 1;
+        }
+        assert_eq!(forwarded, 2);
+        let saved = store.load(&id).expect("session is saved");
+        assert_eq!(saved.usage["gpt-4o-mini"].prompt_tokens, 100);
+        assert_eq!(saved.total_usage().completion_tokens, 40);
+    }
+
+    #[test]
+    fn descendants_include_forks_of_forks() {
+        let sessions = vec![
+            session_file("root", None).summary(),
+            session_file("child_a", Some("root")).summary(),
+            session_file("child_b", Some("root")).summary(),
+            session_file("grandchild", Some("child_a")).summary(),
+            session_file("unrelated", None).summary(),
+        ];
+        let mut ids: Vec<String> = descendants_of("root", &sessions)
+            .into_iter()
+            .map(|s| s.id)
+            .collect();
+        ids.sort();
+        assert_eq!(ids, vec!["child_a", "child_b", "grandchild"]);
+        assert!(descendants_of("grandchild", &sessions).is_empty());
+    }
+}
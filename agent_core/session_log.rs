@@ -0,0 +1,253 @@
+use crate::session::SessionFile;
+use crate::usage::TokenUsage;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Records appended after which a session is saved whole, rewriting its log as a snapshot.
+pub(crate) const COMPACT_AFTER_RECORDS: usize = 500;
+
+/// Bytes read at a time when looking for the end of the last complete record.
+const TAIL_CHUNK: u64 = 4096;
+
+/// A line of a session log. The session is the last snapshot with every later record applied.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum LogRecord {
+    /// The whole session.
+    Snapshot { session: SessionFile },
+    /// A message appended to the session.
+    Message { message: genai::chat::ChatMessage },
+    /// The session fields that change between messages.
+    Update {
+        model: String,
+        updated_at: DateTime<Utc>,
+        usage: BTreeMap<String, TokenUsage>,
+    },
+}
+
+/// A session rebuilt from its log.
+pub(crate) struct ReplayedLog {
+    pub session: SessionFile,
+    /// Bytes up to the end of the last complete record. Past it is a record still being
+    /// written, or one a crash cut short.
+    pub valid_len: u64,
+}
+
+/// Rebuilds the session from the log at `path`. The log is only read, another process may be
+/// appending to it.
+///
+/// Every record ends with a newline. A last line without one is a record being written, or
+/// the trace of a write cut short by a crash: it is skipped, and cut by the next [`append`].
+/// Any other bad line is an error, the log is not guessed around.
+pub(crate) fn replay(path: &Path) -> Result<ReplayedLog> {
+    let content = std::fs::read(path)?;
+    let mut session: Option<SessionFile> = None;
+    let mut offset = 0;
+    while offset < content.len() {
+        let Some(end) = content[offset..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .map(|i| offset + i)
+        else {
+            break;
+        };
+        let line = &content[offset..end];
+        let record: LogRecord = serde_json::from_slice(line).with_context(|| {
+            format!(
+                "Session log {} is corrupted at byte {offset}",
+                path.display()
+            )
+        })?;
+        match (record, session.as_mut()) {
+            (LogRecord::Snapshot { session: snapshot }, _) => {
+                session = Some(snapshot);
+            }
+            (LogRecord::Message { message }, Some(session)) => {
+                session.messages.push(message);
+            }
+            (
+                LogRecord::Update {
+                    model,
+                    updated_at,
+                    usage,
+                },
+                Some(session),
+            ) => {
+                session.model = model;
+                session.updated_at = updated_at;
+                session.usage = usage;
+            }
+            (_, None) => anyhow::bail!(
+                "Session log {} does not start with a snapshot",
+                path.display()
+            ),
+        }
+        offset = end + 1;
+    }
+
+    let session =
+        session.ok_or_else(|| anyhow::anyhow!("Session log {} is empty", path.display()))?;
+    Ok(ReplayedLog {
+        session,
+        valid_len: offset as u64,
+    })
+}
+
+/// Appends the messages of `file` from `from` on, and its changing fields, to the log at
+/// `path`. The records are synced to disk before returning.
+///
+/// Only the process owning the session appends to its log. An incomplete last record left by
+/// a crash is cut first, so the new records start on a clean line.
+pub(crate) fn append(path: &Path, file: &SessionFile, from: usize) -> Result<()> {
+    let mut lines = Vec::new();
+    for message in &file.messages[from.min(file.messages.len())..] {
+        write_record(
+            &mut lines,
+            &LogRecord::Message {
+                message: message.clone(),
+            },
+        )?;
+    }
+    write_record(
+        &mut lines,
+        &LogRecord::Update {
+            model: file.model.clone(),
+            updated_at: file.updated_at,
+            usage: file.usage.clone(),
+        },
+    )?;
+
+    let mut log = std::fs::OpenOptions::new()
+        .read(true)
+        .append(true)
+        .open(path)?;
+    cut_incomplete_record(&mut log, path)?;
+    log.write_all(&lines)?;
+    log.sync_data()?;
+    Ok(())
+}
+
+/// Cuts the log after its last newline, dropping a record a crash cut short.
+fn cut_incomplete_record(log: &mut std::fs::File, path: &Path) -> Result<()> {
+    let len = log.metadata()?.len();
+    let mut end = len;
+    let mut chunk = Vec::new();
+    while end > 0 {
+        let start = end.saturating_sub(TAIL_CHUNK);
+        chunk.resize((end - start) as usize, 0);
+        log.seek(SeekFrom::Start(start))?;
+        log.read_exact(&mut chunk)?;
+        if let Some(i) = chunk.iter().rposition(|b| *b == b'\n') {
+            end = start + i as u64 + 1;
+            break;
+        }
+        end = start;
+    }
+    if end < len {
+        log::warn!(
+            "Cutting the incomplete last record of {} ({} bytes)",
+            path.display(),
+            len - end
+        );
+        log.set_len(end)?;
+        log.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Replaces the log at `path` with a single snapshot of `file`.
+///
+/// The snapshot is written and synced to a temporary file first, then renamed over the log, so
+/// a crash leaves either the old log or the new one.
+pub(crate) fn write_snapshot(path: &Path, file: &SessionFile) -> Result<()> {
+    let mut lines = Vec::new();
+    write_record(
+        &mut lines,
+        &LogRecord::Snapshot {
+            session: file.clone(),
+        },
+    )?;
+
+    let temporary = path.with_extension("jsonl.tmp");
+    {
+        let mut snapshot = std::fs::File::create(&temporary)?;
+        snapshot.write_all(&lines)?;
+        snapshot.sync_all()?;
+    }
+    std::fs::rename(&temporary, path)?;
+    // The rename itself is only durable once the directory is synced. Directories cannot be
+    // opened for syncing on every platform, the snapshot is still consistent without it.
+    if let Some(dir) = path.parent()
+        && let Ok(dir) = std::fs::File::open(dir)
+    {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+fn write_record(buffer: &mut Vec<u8>, record: &LogRecord) -> Result<()> {
+    serde_json::to_writer(&mut *buffer, record)?;
+    buffer.push(b'\n');
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{TempDir, session_file};
+    use genai::chat::ChatMessage;
+
+    #[test]
+    fn truncated_last_record_is_skipped_and_cut_by_the_next_append() {
+        let dir = TempDir::new("session-log");
+        let path = dir.path().join("log.jsonl");
+        let mut file = session_file("log", vec![ChatMessage::user("first")]);
+        write_snapshot(&path, &file).expect("snapshot is written");
+        file.messages.push(ChatMessage::assistant("second"));
+        file.model = String::from("gpt-4o-mini");
+        append(&path, &file, 1).expect("message is appended");
+
+        // A crash in the middle of the next append
+        let mut log = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .expect("log opens");
+        log.write_all(br#"{"type":"message","message":{"ro"#)
+            .expect("partial record is written");
+
+        let len = std::fs::metadata(&path).expect("log exists").len();
+        let replayed = replay(&path).expect("log is recovered");
+        assert_eq!(replayed.session.messages.len(), 2);
+        assert_eq!(replayed.session.model, "gpt-4o-mini");
+        assert!(replayed.valid_len < len);
+        // Reading leaves the log alone, a writer may be in the middle of that record
+        assert_eq!(std::fs::metadata(&path).expect("log exists").len(), len);
+
+        file.messages.push(ChatMessage::user("third"));
+        append(&path, &file, 2).expect("appends continue on a clean line");
+        assert_eq!(
+            replay(&path).expect("log replays").session.messages.len(),
+            3
+        );
+    }
+
+    #[test]
+    fn corruption_before_the_last_record_is_an_error() {
+        let dir = TempDir::new("session-log");
+        let path = dir.path().join("log.jsonl");
+        let file = session_file("log", vec![ChatMessage::user("first")]);
+        write_snapshot(&path, &file).expect("snapshot is written");
+        let mut log = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .expect("log opens");
+        log.write_all(b"not json\n").expect("garbage is written");
+        append(&path, &file, 1).expect("update is appended");
+
+        assert!(replay(&path).is_err());
+    }
+}
@@ -0,0 +1,854 @@
+use crate::session::{SessionFile, SessionSummary};
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Name of the SQLite database in the sessions directory.
+const SESSIONS_DB: &str = "sessions.db";
+
+/// Characters of context `JsonSessionStore::search` keeps on each side of a match.
+const SNIPPET_CONTEXT: usize = 40;
+
+/// A message matching a search query.
+#[derive(Debug, Clone)]
+pub struct SessionSearchHit {
+    pub session_id: String,
+    /// Index of the message in the session, as numbered by `session show`.
+    pub message_index: usize,
+    /// The matching part of the message text, matched terms between `[` and `]`.
+    pub snippet: String,
+}
+
+/// Where sessions are saved. Tool outputs spilled during a session live next to it, in
+/// [`SessionStore::outputs_dir`], whatever the store.
+pub trait SessionStore: std::fmt::Debug + Send + Sync {
+    /// Insert the session, or replace the saved one with the same id.
+    fn save(&self, file: &SessionFile) -> Result<()>;
+
+    /// Save the session, whose messages from `from` on were added since it was last saved or
+    /// loaded. Stores keeping a log append them, the others save the whole session.
+    fn append(&self, file: &SessionFile, from: usize) -> Result<()> {
+        let _ = from;
+        self.save(file)
+    }
+
+    fn load(&self, session_id: &str) -> Result<SessionFile>;
+
+    /// Every session, most recently updated first.
+    fn list(&self) -> Result<Vec<SessionSummary>>;
+
+    /// Delete the session and its saved tool outputs.
+    fn delete(&self, session_id: &str) -> Result<()>;
+
+    /// Messages whose text contains every word of `query`, best matches first.
+    fn search(&self, query: &str, limit: usize) -> Result<Vec<SessionSearchHit>>;
+
+    /// Directory of the large tool outputs saved during the session.
+    fn outputs_dir(&self, session_id: &str) -> Result<PathBuf>;
+}
+
+/// Checks that `session_id` is safe to use as a file name: letters, digits, `-` and `_`, which
+/// covers the generated uuids.
+fn checked_id(session_id: &str) -> Result<&str> {
+    anyhow::ensure!(
+        !session_id.is_empty()
+            && session_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_')),
+        "Invalid session id '{session_id}'"
+    );
+    Ok(session_id)
+}
+
+fn sessions_dir() -> Result<PathBuf> {
+    let storage = config::application_storage(true)?.join("sessions");
+    if !storage.exists() {
+        std::fs::create_dir_all(storage.clone())?;
+    }
+    Ok(storage)
+}
+
+/// Opens the store selected in the config, in the application storage.
+///
+/// The SQLite database imports the JSON sessions when it is created, so switching stores keeps
+/// the history. Sessions saved as JSON after that are imported with [`migrate_json_sessions`],
+/// a warning is logged while there are some.
+pub fn open_session_store(kind: config::SessionStoreKind) -> Result<Arc<dyn SessionStore>> {
+    let dir = sessions_dir()?;
+    let json = JsonSessionStore::new(&dir);
+    match kind {
+        config::SessionStoreKind::Json => Ok(Arc::new(json)),
+        config::SessionStoreKind::Sqlite => {
+            let path = dir.join(SESSIONS_DB);
+            let created = !path.exists();
+            let store = SqliteSessionStore::open(&path)?;
+            if created {
+                let imported = store.migrate_from(&json)?;
+                if imported > 0 {
+                    log::info!("Imported {imported} JSON sessions into {}", path.display());
+                }
+            } else {
+                let missing = store.count_missing(&json.ids()?)?;
+                if missing > 0 {
+                    log::warn!(
+                        "{missing} sessions saved as JSON are not in {}, import them with `session migrate`",
+                        path.display()
+                    );
+                }
+            }
+            Ok(Arc::new(store))
+        }
+    }
+}
+
+/// Imports the sessions saved as JSON files into the SQLite database, skipping those already
+/// there. Returns how many were imported.
+pub fn migrate_json_sessions() -> Result<usize> {
+    let dir = sessions_dir()?;
+    SqliteSessionStore::open(&dir.join(SESSIONS_DB))?.migrate_from(&JsonSessionStore::new(&dir))
+}
+
+fn remove_outputs(dir: &Path) -> Result<()> {
+    if dir.exists() {
+        std::fs::remove_dir_all(dir)?;
+    }
+    Ok(())
+}
+
+/// Text of a message worth searching: what the user and the assistant wrote, not the tool
+/// calls and results.
+fn message_text(message: &genai::chat::ChatMessage) -> Option<String> {
+    match message.role {
+        genai::chat::ChatRole::User | genai::chat::ChatRole::Assistant => message
+            .content
+            .joined_texts()
+            .filter(|text| !text.trim().is_empty()),
+        _ => None,
+    }
+}
+
+/// One append-only JSON lines log per session, `<id>.jsonl`, synced to disk on every write.
+/// Saving a session writes a snapshot replacing its log, appending writes the new messages
+/// only. Listing and searching replay every log, which is why [`SqliteSessionStore`] is the
+/// default.
+///
+/// Sessions saved as a single `<id>.json` file by earlier versions are still read. Their
+/// first save turns them into a log.
+#[derive(Debug, Clone)]
+pub struct JsonSessionStore {
+    dir: PathBuf,
+}
+
+impl JsonSessionStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, session_id: &str) -> Result<PathBuf> {
+        Ok(self.dir.join(format!("{}.jsonl", checked_id(session_id)?)))
+    }
+
+    fn legacy_path(&self, session_id: &str) -> Result<PathBuf> {
+        Ok(self.dir.join(format!("{}.json", checked_id(session_id)?)))
+    }
+
+    /// Ids of the sessions in the directory, logs and legacy files alike.
+    fn ids(&self) -> Result<Vec<String>> {
+        let mut ids: Vec<String> = std::fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                path.extension()
+                    .is_some_and(|ext| ext == "jsonl" || ext == "json")
+                    .then(|| path.file_stem()?.to_str().map(str::to_string))
+                    .flatten()
+            })
+            .collect();
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    /// Every readable session, most recently updated first.
+    fn load_all(&self) -> Result<Vec<SessionFile>> {
+        let mut sessions: Vec<SessionFile> = self
+            .ids()?
+            .iter()
+            .filter_map(|id| match self.load(id) {
+                Ok(file) => Some(file),
+                Err(e) => {
+                    log::warn!("Skipping unreadable session {id}: {e}");
+                    None
+                }
+            })
+            .collect();
+        sessions.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+        Ok(sessions)
+    }
+
+    /// Rewrites the log of the session as a single snapshot.
+    pub fn compact(&self, session_id: &str) -> Result<()> {
+        let file = self.load(session_id)?;
+        self.save(&file)
+    }
+}
+
+impl SessionStore for JsonSessionStore {
+    fn save(&self, file: &SessionFile) -> Result<()> {
+        crate::session_log::write_snapshot(&self.path(&file.id)?, file)?;
+        let legacy = self.legacy_path(&file.id)?;
+        if legacy.exists() {
+            std::fs::remove_file(legacy)?;
+        }
+        Ok(())
+    }
+
+    fn append(&self, file: &SessionFile, from: usize) -> Result<()> {
+        let path = self.path(&file.id)?;
+        if path.exists() {
+            crate::session_log::append(&path, file, from)
+        } else {
+            self.save(file)
+        }
+    }
+
+    fn load(&self, session_id: &str) -> Result<SessionFile> {
+        let path = self.path(session_id)?;
+        if !path.exists() {
+            let reader =
+                std::io::BufReader::new(std::fs::File::open(self.legacy_path(session_id)?)?);
+            return Ok(serde_json::from_reader(reader)?);
+        }
+        let replayed = crate::session_log::replay(&path)?;
+        if replayed.valid_len < std::fs::metadata(&path)?.len() {
+            log::warn!(
+                "Ignoring the incomplete last record of {}, still being written or cut by a crash",
+                path.display()
+            );
+        }
+        Ok(replayed.session)
+    }
+
+    fn list(&self) -> Result<Vec<SessionSummary>> {
+        Ok(self.load_all()?.iter().map(SessionFile::summary).collect())
+    }
+
+    fn delete(&self, session_id: &str) -> Result<()> {
+        let outputs_dir = self.outputs_dir(session_id)?;
+        let mut deleted = false;
+        for path in [self.path(session_id)?, self.legacy_path(session_id)?] {
+            if path.exists() {
+                std::fs::remove_file(path)?;
+                deleted = true;
+            }
+        }
+        anyhow::ensure!(deleted, "Session {session_id} not found");
+        remove_outputs(&outputs_dir)
+    }
+
+    fn search(&self, query: &str, limit: usize) -> Result<Vec<SessionSearchHit>> {
+        let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        if terms.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut hits = Vec::new();
+        for file in self.load_all()? {
+            for (index, message) in file.messages.iter().enumerate() {
+                let Some(text) = message_text(message) else {
+                    continue;
+                };
+                let lowercase = text.to_lowercase();
+                if !terms.iter().all(|term| lowercase.contains(term.as_str())) {
+                    continue;
+                }
+                hits.push(SessionSearchHit {
+                    session_id: file.id.clone(),
+                    message_index: index,
+                    snippet: snippet(&text, &terms[0]),
+                });
+                if hits.len() >= limit {
+                    return Ok(hits);
+                }
+            }
+        }
+        Ok(hits)
+    }
+
+    fn outputs_dir(&self, session_id: &str) -> Result<PathBuf> {
+        Ok(self.dir.join(checked_id(session_id)?))
+    }
+}
+
+/// The part of `text` around the first match of `term`, which must be lowercase.
+fn snippet(text: &str, term: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let lowercase: Vec<char> = chars.iter().flat_map(|c| c.to_lowercase()).collect();
+    let term: Vec<char> = term.chars().collect();
+    // Lowercasing may change the length of the text, the match is then left unmarked
+    let start = (lowercase.len() == chars.len())
+        .then(|| {
+            lowercase
+                .windows(term.len())
+                .position(|w| w == term.as_slice())
+        })
+        .flatten();
+    let Some(start) = start else {
+        return chars.iter().take(2 * SNIPPET_CONTEXT).collect();
+    };
+    let end = start + term.len();
+    let from = start.saturating_sub(SNIPPET_CONTEXT);
+    let to = (end + SNIPPET_CONTEXT).min(chars.len());
+    format!(
+        "{}{}[{}]{}{}",
+        if from > 0 { "…" } else { "" },
+        chars[from..start].iter().collect::<String>(),
+        chars[start..end].iter().collect::<String>(),
+        chars[end..to].iter().collect::<String>(),
+        if to < chars.len() { "…" } else { "" },
+    )
+    .replace('\n', " ")
+}
+
+/// Sessions in a SQLite database, with an FTS5 index over the text of their messages. The
+/// fields shown by listings are kept in columns next to the session JSON, and the messages in
+/// rows of their own, so appending to a session only writes its new messages.
+#[derive(Debug)]
+pub struct SqliteSessionStore {
+    connection: Mutex<rusqlite::Connection>,
+    dir: PathBuf,
+}
+
+impl SqliteSessionStore {
+    /// Opens the database at `path`, creating it if needed. Tool outputs are saved in the
+    /// directory of the database.
+    pub fn open(path: &Path) -> Result<Self> {
+        let connection = rusqlite::Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS sessions (
+                id TEXT PRIMARY KEY,
+                parent_id TEXT,
+                updated_at TEXT NOT NULL,
+                data TEXT NOT NULL,
+                model TEXT NOT NULL DEFAULT '',
+                created_at TEXT NOT NULL DEFAULT '',
+                forked_at INTEGER,
+                message_count INTEGER NOT NULL DEFAULT 0,
+                first_user_line TEXT
+            );
+            CREATE INDEX IF NOT EXISTS sessions_updated_at ON sessions (updated_at);
+            CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5 (
+                session_id UNINDEXED,
+                message_index UNINDEXED,
+                text
+            );",
+        )?;
+        let store = Self {
+            connection: Mutex::new(connection),
+            dir: path
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| PathBuf::from(".")),
+        };
+        store.add_summary_columns()?;
+        store.add_message_rows()?;
+        Ok(store)
+    }
+
+    /// Adds the summary columns to a database created before they existed. They are filled
+    /// from the session JSON by [`Self::add_message_rows`], which every such database needs.
+    fn add_summary_columns(&self) -> Result<()> {
+        let mut connection = self.connection()?;
+        let has_columns = connection
+            .prepare("SELECT 1 FROM pragma_table_info('sessions') WHERE name = 'message_count'")?
+            .exists([])?;
+        if has_columns {
+            return Ok(());
+        }
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(
+            "ALTER TABLE sessions ADD COLUMN model TEXT NOT NULL DEFAULT '';
+             ALTER TABLE sessions ADD COLUMN created_at TEXT NOT NULL DEFAULT '';
+             ALTER TABLE sessions ADD COLUMN forked_at INTEGER;
+             ALTER TABLE sessions ADD COLUMN message_count INTEGER NOT NULL DEFAULT 0;
+             ALTER TABLE sessions ADD COLUMN first_user_line TEXT;
+             UPDATE sessions SET created_at = updated_at;",
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Moves the messages of a database created before they had rows of their own out of the
+    /// session JSON. Unreadable sessions are left as they are, and still fail to load.
+    fn add_message_rows(&self) -> Result<()> {
+        let mut connection = self.connection()?;
+        let has_table = connection
+            .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'messages'")?
+            .exists([])?;
+        if has_table {
+            return Ok(());
+        }
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(
+            "CREATE TABLE messages (
+                session_id TEXT NOT NULL,
+                message_index INTEGER NOT NULL,
+                data TEXT NOT NULL,
+                PRIMARY KEY (session_id, message_index)
+            ) WITHOUT ROWID;",
+        )?;
+        let sessions = transaction
+            .prepare("SELECT data FROM sessions")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        for data in sessions {
+            match serde_json::from_str::<SessionFile>(&data) {
+                Ok(file) => {
+                    write_row(&transaction, &file)?;
+                    write_messages(&transaction, &file, 0)?;
+                }
+                Err(e) => log::warn!("Skipping unreadable session: {e}"),
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn connection(&self) -> Result<std::sync::MutexGuard<'_, rusqlite::Connection>> {
+        self.connection
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Session database lock poisoned: {e}"))
+    }
+
+    /// How many of the sessions `ids` are not in the database.
+    fn count_missing(&self, ids: &[String]) -> Result<usize> {
+        let connection = self.connection()?;
+        let mut exists = connection.prepare("SELECT 1 FROM sessions WHERE id = ?1")?;
+        let mut missing = 0;
+        for id in ids {
+            if !exists.exists([id])? {
+                missing += 1;
+            }
+        }
+        Ok(missing)
+    }
+
+    /// Copies the sessions of `other` missing from this store. Returns how many were copied.
+    pub fn migrate_from(&self, other: &dyn SessionStore) -> Result<usize> {
+        let mut migrated = 0;
+        for summary in other.list()? {
+            let exists = self.connection()?.query_row(
+                "SELECT EXISTS (SELECT 1 FROM sessions WHERE id = ?1)",
+                [&summary.id],
+                |row| row.get::<_, bool>(0),
+            )?;
+            if !exists {
+                self.save(&other.load(&summary.id)?)?;
+                migrated += 1;
+            }
+        }
+        Ok(migrated)
+    }
+}
+
+/// Each word of `query` as an FTS5 string, so the query is matched as plain words rather
+/// than parsed as FTS5 syntax.
+fn fts_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Updates the row of `file`, everything but its messages. Returns the number of rows updated,
+/// 0 when it is not saved.
+fn write_row(transaction: &rusqlite::Transaction<'_>, file: &SessionFile) -> Result<usize> {
+    let fields = SessionFile {
+        id: file.id.clone(),
+        model: file.model.clone(),
+        created_at: file.created_at,
+        updated_at: file.updated_at,
+        messages: Vec::new(),
+        parent_id: file.parent_id.clone(),
+        forked_at: file.forked_at,
+        usage: file.usage.clone(),
+    };
+    Ok(transaction.execute(
+        "UPDATE sessions SET parent_id = ?2, updated_at = ?3, data = ?4, model = ?5,
+            created_at = ?6, forked_at = ?7, message_count = ?8, first_user_line = ?9
+         WHERE id = ?1",
+        rusqlite::params![
+            file.id,
+            file.parent_id,
+            timestamp(&file.updated_at),
+            serde_json::to_string(&fields)?,
+            file.model,
+            timestamp(&file.created_at),
+            file.forked_at.map(|at| at as i64),
+            file.messages.len() as i64,
+            file.first_user_line(),
+        ],
+    )?)
+}
+
+/// Timestamps are stored with a fixed precision, so they sort as text.
+fn timestamp(time: &chrono::DateTime<chrono::Utc>) -> String {
+    time.to_rfc3339_opts(chrono::SecondsFormat::Micros, true)
+}
+
+fn parse_timestamp(text: &str) -> rusqlite::Result<chrono::DateTime<chrono::Utc>> {
+    chrono::DateTime::parse_from_rfc3339(text)
+        .map(|time| time.with_timezone(&chrono::Utc))
+        .map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, e.into())
+        })
+}
+
+/// Saves the messages of `file` from `from` on, replacing those saved with the same index.
+fn write_messages(
+    transaction: &rusqlite::Transaction<'_>,
+    file: &SessionFile,
+    from: usize,
+) -> Result<()> {
+    transaction.execute(
+        "DELETE FROM messages WHERE session_id = ?1 AND message_index >= ?2",
+        rusqlite::params![file.id, from as i64],
+    )?;
+    let mut insert = transaction
+        .prepare("INSERT INTO messages (session_id, message_index, data) VALUES (?1, ?2, ?3)")?;
+    for (index, message) in file.messages.iter().enumerate().skip(from) {
+        insert.execute(rusqlite::params![
+            file.id,
+            index as i64,
+            serde_json::to_string(message)?
+        ])?;
+    }
+    Ok(())
+}
+
+/// Adds the text of the messages of `file` from `from` on to the search index.
+fn index_messages(
+    transaction: &rusqlite::Transaction<'_>,
+    file: &SessionFile,
+    from: usize,
+) -> Result<()> {
+    let mut insert = transaction.prepare(
+        "INSERT INTO messages_fts (session_id, message_index, text) VALUES (?1, ?2, ?3)",
+    )?;
+    for (index, message) in file.messages.iter().enumerate().skip(from) {
+        if let Some(text) = message_text(message) {
+            insert.execute(rusqlite::params![file.id, index as i64, text])?;
+        }
+    }
+    Ok(())
+}
+
+impl SessionStore for SqliteSessionStore {
+    fn save(&self, file: &SessionFile) -> Result<()> {
+        let mut connection = self.connection()?;
+        let transaction = connection.transaction()?;
+        // Inserted empty, then filled like an update
+        transaction.execute(
+            "INSERT INTO sessions (id, updated_at, data) VALUES (?1, '', '')
+             ON CONFLICT (id) DO NOTHING",
+            [&file.id],
+        )?;
+        write_row(&transaction, file)?;
+        write_messages(&transaction, file, 0)?;
+        transaction.execute("DELETE FROM messages_fts WHERE session_id = ?1", [&file.id])?;
+        index_messages(&transaction, file, 0)?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Updates the fields of the session, and writes and indexes only the messages from `from`
+    /// on.
+    fn append(&self, file: &SessionFile, from: usize) -> Result<()> {
+        let mut connection = self.connection()?;
+        let transaction = connection.transaction()?;
+        if write_row(&transaction, file)? == 0 {
+            drop(transaction);
+            drop(connection);
+            return self.save(file);
+        }
+        write_messages(&transaction, file, from)?;
+        index_messages(&transaction, file, from)?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn load(&self, session_id: &str) -> Result<SessionFile> {
+        let connection = self.connection()?;
+        let data: String = connection
+            .query_row(
+                "SELECT data FROM sessions WHERE id = ?1",
+                [session_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => {
+                    anyhow::anyhow!("Session {session_id} not found")
+                }
+                e => e.into(),
+            })?;
+        let mut file: SessionFile = serde_json::from_str(&data)?;
+        file.messages = connection
+            .prepare("SELECT data FROM messages WHERE session_id = ?1 ORDER BY message_index")?
+            .query_map([session_id], |row| row.get::<_, String>(0))?
+            .map(|data| Ok(serde_json::from_str(&data?)?))
+            .collect::<Result<_>>()?;
+        Ok(file)
+    }
+
+    fn list(&self) -> Result<Vec<SessionSummary>> {
+        let connection = self.connection()?;
+        let mut statement = connection.prepare(
+            "SELECT id, model, created_at, updated_at, message_count, parent_id, forked_at,
+                first_user_line
+             FROM sessions ORDER BY updated_at DESC",
+        )?;
+        let sessions = statement
+            .query_map([], |row| {
+                Ok(SessionSummary {
+                    id: row.get(0)?,
+                    model: row.get(1)?,
+                    created_at: parse_timestamp(&row.get::<_, String>(2)?)?,
+                    updated_at: parse_timestamp(&row.get::<_, String>(3)?)?,
+                    message_count: row.get::<_, i64>(4)? as usize,
+                    parent_id: row.get(5)?,
+                    forked_at: row.get::<_, Option<i64>>(6)?.map(|at| at as usize),
+                    first_user_line: row.get(7)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(sessions)
+    }
+
+    fn delete(&self, session_id: &str) -> Result<()> {
+        let outputs_dir = self.outputs_dir(session_id)?;
+        {
+            let mut connection = self.connection()?;
+            let transaction = connection.transaction()?;
+            let deleted =
+                transaction.execute("DELETE FROM sessions WHERE id = ?1", [session_id])?;
+            anyhow::ensure!(deleted > 0, "Session {session_id} not found");
+            transaction.execute("DELETE FROM messages WHERE session_id = ?1", [session_id])?;
+            transaction.execute(
+                "DELETE FROM messages_fts WHERE session_id = ?1",
+                [session_id],
+            )?;
+            transaction.commit()?;
+        }
+        remove_outputs(&outputs_dir)
+    }
+
+    fn search(&self, query: &str, limit: usize) -> Result<Vec<SessionSearchHit>> {
+        let query = fts_query(query);
+        if query.is_empty() {
+            return Ok(vec![]);
+        }
+        let connection = self.connection()?;
+        let mut statement = connection.prepare(
+            "SELECT session_id, message_index, snippet(messages_fts, 2, '[', ']', '…', 16)
+             FROM messages_fts WHERE messages_fts MATCH ?1 ORDER BY rank LIMIT ?2",
+        )?;
+        let hits = statement
+            .query_map(rusqlite::params![query, limit as i64], |row| {
+                Ok(SessionSearchHit {
+                    session_id: row.get(0)?,
+                    message_index: row.get::<_, i64>(1)? as usize,
+                    snippet: row.get::<_, String>(2)?.replace('\n', " "),
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(hits)
+    }
+
+    fn outputs_dir(&self, session_id: &str) -> Result<PathBuf> {
+        Ok(self.dir.join(checked_id(session_id)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{TempDir, session_file};
+    use genai::chat::ChatMessage;
+
+    #[test]
+    fn sqlite_store_searches_message_text() {
+        let dir = TempDir::new("session-store");
+        let store =
+            SqliteSessionStore::open(&dir.path().join(SESSIONS_DB)).expect("database opens");
+        store
+            .save(&session_file(
+                "weather",
+                vec![
+                    ChatMessage::user("Will it rain in Brooklyn tomorrow?"),
+                    ChatMessage::assistant("Light rain is expected in the afternoon."),
+                ],
+            ))
+            .expect("session is saved");
+        store
+            .save(&session_file(
+                "recall",
+                vec![ChatMessage::user(
+                    "Quiz me on \"borrow\" rules, it's raining",
+                )],
+            ))
+            .expect("session is saved");
+
+        let hits = store.search("rain brooklyn", 10).expect("search runs");
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].session_id, "weather");
+        assert_eq!(hits[0].message_index, 0);
+        assert!(
+            hits[0].snippet.contains("[Brooklyn]"),
+            "{}",
+            hits[0].snippet
+        );
+        assert_eq!(
+            store
+                .search("\"borrow\" AND", 10)
+                .expect("syntax is escaped")
+                .len(),
+            0
+        );
+
+        // Appending indexes the new messages only
+        let mut weather = store.load("weather").expect("session loads");
+        weather
+            .messages
+            .push(ChatMessage::user("And in Queens, will it rain?"));
+        store.append(&weather, 2).expect("message is appended");
+        assert_eq!(
+            store.load("weather").expect("session loads").messages.len(),
+            3
+        );
+        let data: String = store
+            .connection()
+            .expect("connection")
+            .query_row(
+                "SELECT data FROM sessions WHERE id = 'weather'",
+                [],
+                |row| row.get(0),
+            )
+            .expect("row is read");
+        assert!(!data.contains("Queens"), "messages have their own rows");
+        assert_eq!(store.search("queens", 10).expect("search runs").len(), 1);
+        assert_eq!(
+            store
+                .search("rain brooklyn", 10)
+                .expect("search runs")
+                .len(),
+            1
+        );
+        let summaries = store.list().expect("list");
+        let summary = summaries
+            .iter()
+            .find(|s| s.id == "weather")
+            .expect("session is listed");
+        assert_eq!(summary.message_count, 3);
+        assert_eq!(
+            summary.first_user_line.as_deref(),
+            Some("Will it rain in Brooklyn tomorrow?")
+        );
+
+        store.delete("weather").expect("session is deleted");
+        assert!(store.search("rain", 10).expect("search runs").is_empty());
+        assert_eq!(store.list().expect("list").len(), 1);
+    }
+
+    #[test]
+    fn summary_columns_are_added_to_older_databases() {
+        let dir = TempDir::new("session-store");
+        let path = dir.path().join(SESSIONS_DB);
+        let file = session_file("old", vec![ChatMessage::user("Saved before summaries")]);
+        let connection = rusqlite::Connection::open(&path).expect("database opens");
+        connection
+            .execute_batch(
+                "CREATE TABLE sessions (
+                    id TEXT PRIMARY KEY,
+                    parent_id TEXT,
+                    updated_at TEXT NOT NULL,
+                    data TEXT NOT NULL
+                );",
+            )
+            .expect("old schema is created");
+        connection
+            .execute(
+                "INSERT INTO sessions (id, updated_at, data) VALUES (?1, ?2, ?3)",
+                rusqlite::params![
+                    file.id,
+                    timestamp(&file.updated_at),
+                    serde_json::to_string(&file).expect("session serializes")
+                ],
+            )
+            .expect("session is saved");
+        drop(connection);
+
+        let store = SqliteSessionStore::open(&path).expect("database is upgraded");
+        let summaries = store.list().expect("list");
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].message_count, 1);
+        assert_eq!(
+            summaries[0].first_user_line.as_deref(),
+            Some("Saved before summaries")
+        );
+        assert_eq!(store.load("old").expect("session loads").messages.len(), 1);
+    }
+
+    #[test]
+    fn ids_that_are_not_file_names_are_rejected() {
+        let dir = TempDir::new("session-store");
+        let outside = dir.path().join("outside");
+        std::fs::create_dir_all(&outside).expect("directory is created");
+        let sessions = dir.path().join("sessions");
+        std::fs::create_dir_all(&sessions).expect("directory is created");
+        let json = JsonSessionStore::new(&sessions);
+        let sqlite = SqliteSessionStore::open(&sessions.join(SESSIONS_DB)).expect("database opens");
+
+        for store in [&json as &dyn SessionStore, &sqlite] {
+            assert!(store.delete("../outside").is_err());
+            assert!(store.outputs_dir("../outside").is_err());
+            assert!(store.load("../outside").is_err());
+        }
+        assert!(json.save(&session_file("a/b", vec![])).is_err());
+        assert!(outside.exists());
+        assert!(json.outputs_dir(&uuid::Uuid::new_v4().to_string()).is_ok());
+    }
+
+    #[test]
+    fn json_sessions_migrate_once() {
+        let dir = TempDir::new("session-store");
+        let json = JsonSessionStore::new(dir.path());
+        json.save(&session_file("one", vec![ChatMessage::user("hello there")]))
+            .expect("session is saved");
+        json.save(&session_file("two", vec![]))
+            .expect("session is saved");
+
+        let sqlite =
+            SqliteSessionStore::open(&dir.path().join(SESSIONS_DB)).expect("database opens");
+        let ids = json.ids().expect("ids are listed");
+        assert_eq!(sqlite.count_missing(&ids).expect("count runs"), 2);
+        assert_eq!(sqlite.migrate_from(&json).expect("migration runs"), 2);
+        assert_eq!(sqlite.migrate_from(&json).expect("migration runs"), 0);
+        assert_eq!(sqlite.count_missing(&ids).expect("count runs"), 0);
+        assert_eq!(
+            sqlite
+                .load("one")
+                .expect("session is migrated")
+                .messages
+                .len(),
+            1
+        );
+        assert_eq!(
+            json.search("HELLO", 10).expect("search runs")[0].snippet,
+            "[hello] there"
+        );
+        assert_eq!(
+            sqlite.search("hello", 10).expect("search runs")[0].session_id,
+            "one"
+        );
+    }
+}
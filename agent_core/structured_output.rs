@@ -0,0 +1,245 @@
+/// The JSON the final answer of the agent loop must be, see
+/// [`crate::AgentLoopConfig::response_schema`] and [`crate::agent_loop_structured`].
+#[derive(Debug, Clone)]
+pub struct ResponseSchema {
+    /// Name sent with the schema to the providers that take one. Letters, digits, `-` and `_`.
+    pub name: String,
+
+    pub schema: serde_json::Value,
+
+    /// Answers sent back with their violations before the loop gives up. Default: 2.
+    pub max_retries: usize,
+}
+
+impl ResponseSchema {
+    pub fn new(name: &str, schema: serde_json::Value) -> Self {
+        Self {
+            name: name.to_string(),
+            schema,
+            max_retries: 2,
+        }
+    }
+
+    /// The schema of `T`, for answers read with `serde_json::from_value::<T>`.
+    ///
+    /// genai sends response schemas to OpenAI in strict mode, which wants every property
+    /// required and no other properties. The schema is changed to match: `Option` fields,
+    /// which accept `null`, are listed as required like the others, and so are fields with a
+    /// serde default.
+    pub fn for_type<T: schemars::JsonSchema>(name: &str) -> Self {
+        let mut schema = crate::tool_schema::schema_for::<T>();
+        make_strict(&mut schema);
+        Self::new(name, schema)
+    }
+
+    pub(crate) fn response_format(&self) -> genai::chat::ChatResponseFormat {
+        genai::chat::JsonSpec::new(self.name.clone(), self.schema.clone()).into()
+    }
+
+    /// The answer in `text` when it is JSON matching the schema, or the reasons it is not, as
+    /// sent back to the LLM. Fails when the schema itself is invalid.
+    pub(crate) fn check(&self, text: &str) -> anyhow::Result<Result<serde_json::Value, String>> {
+        let validator = jsonschema::validator_for(&self.schema)
+            .map_err(|e| anyhow::anyhow!("Response schema '{}' is invalid: {e}", self.name))?;
+
+        let value: serde_json::Value = match serde_json::from_str(strip_code_fence(text)) {
+            Ok(value) => value,
+            Err(e) => return Ok(Err(format!("The answer is not valid JSON: {e}"))),
+        };
+        let violations = crate::tool_schema::schema_violations(&validator, &value);
+        if violations.is_empty() {
+            return Ok(Ok(value));
+        }
+        Ok(Err(serde_json::json!({
+            "error": "invalid_response",
+            "message": format!("The answer does not match the response schema '{}'", self.name),
+            "violations": violations,
+        })
+        .to_string()))
+    }
+
+    /// The message asking for the final answer in the schema, or for a corrected one when the
+    /// previous answer had `violations`.
+    pub(crate) fn request_message(&self, violations: Option<&str>) -> genai::chat::ChatMessage {
+        let schema = self.schema.to_string();
+        genai::chat::ChatMessage::user(match violations {
+            None => format!(
+                "Reply with your final answer as a single JSON value matching this schema, with no other text:\n{schema}"
+            ),
+            Some(violations) => format!(
+                "Your answer was rejected: {violations}\nReply again with only the corrected JSON value, matching this schema:\n{schema}"
+            ),
+        })
+    }
+}
+
+/// Requires every property of the objects in `schema`, and rejects the undeclared ones.
+fn make_strict(schema: &mut serde_json::Value) {
+    let Some(schema) = schema.as_object_mut() else {
+        return;
+    };
+    if let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) {
+        let names: Vec<serde_json::Value> = properties.keys().cloned().map(Into::into).collect();
+        schema.insert(String::from("required"), names.into());
+        schema.insert(String::from("additionalProperties"), false.into());
+    }
+    for (key, value) in schema.iter_mut() {
+        match (key.as_str(), value) {
+            ("properties" | "$defs" | "definitions", serde_json::Value::Object(schemas)) => {
+                schemas.values_mut().for_each(make_strict);
+            }
+            ("anyOf" | "oneOf" | "allOf" | "prefixItems", serde_json::Value::Array(schemas)) => {
+                schemas.iter_mut().for_each(make_strict);
+            }
+            ("items", items) => make_strict(items),
+            _ => {}
+        }
+    }
+}
+
+/// The text inside a markdown code fence, which models put around JSON out of habit.
+fn strip_code_fence(text: &str) -> &str {
+    let text = text.trim();
+    let Some(inner) = text
+        .strip_prefix("```")
+        .and_then(|rest| rest.strip_suffix("```"))
+    else {
+        return text;
+    };
+    // Drop the language tag on the opening line
+    inner
+        .split_once('\n')
+        .map_or(inner, |(_, body)| body)
+        .trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::run_loop;
+    use crate::{AgentLoopConfig, ScriptedBackend, ScriptedResponse};
+
+    #[derive(serde::Deserialize, schemars::JsonSchema)]
+    #[allow(dead_code)]
+    struct Briefing {
+        headline: String,
+        items: Vec<String>,
+    }
+
+    #[derive(serde::Deserialize, schemars::JsonSchema)]
+    #[allow(dead_code)]
+    struct Forecast {
+        city: String,
+        rain: Option<bool>,
+        #[serde(default)]
+        alerts: Vec<Alert>,
+    }
+
+    #[derive(serde::Deserialize, schemars::JsonSchema)]
+    #[allow(dead_code)]
+    struct Alert {
+        level: u8,
+        note: Option<String>,
+    }
+
+    #[test]
+    fn fenced_answers_are_accepted() {
+        let schema = ResponseSchema::for_type::<Briefing>("briefing");
+        let answer = "```json\n{\"headline\": \"Rain\", \"items\": []}\n```";
+        let value = schema
+            .check(answer)
+            .expect("schema is valid")
+            .expect("answer matches");
+        assert_eq!(value["headline"], "Rain");
+    }
+
+    #[test]
+    fn typed_schemas_pass_strict_mode() {
+        let schema = ResponseSchema::for_type::<Forecast>("forecast").schema;
+        assert_eq!(
+            schema["required"],
+            serde_json::json!(["alerts", "city", "rain"])
+        );
+        assert_eq!(schema["additionalProperties"], false);
+        assert_eq!(
+            schema["properties"]["rain"]["type"],
+            serde_json::json!(["boolean", "null"])
+        );
+        let alert = &schema["properties"]["alerts"]["items"];
+        assert_eq!(alert["required"], serde_json::json!(["level", "note"]));
+        assert_eq!(alert["additionalProperties"], false);
+
+        let schema = ResponseSchema::for_type::<Forecast>("forecast");
+        let answer =
+            r#"{"city": "Brooklyn", "rain": null, "alerts": [{"level": 1, "note": null}]}"#;
+        let value = schema
+            .check(answer)
+            .expect("schema is valid")
+            .expect("null is accepted for optional fields");
+        let forecast: Forecast = serde_json::from_value(value).expect("answer deserializes");
+        assert!(forecast.rain.is_none());
+        assert!(
+            schema
+                .check(r#"{"city": "Brooklyn", "alerts": []}"#)
+                .expect("schema is valid")
+                .is_err(),
+            "optional fields are still required"
+        );
+    }
+
+    #[tokio::test]
+    async fn malformed_answers_are_sent_back_with_their_violations() {
+        let backend = ScriptedBackend::new([
+            ScriptedResponse::text("Rain all day."),
+            ScriptedResponse::text(r#"{"headline": "Rain"}"#),
+            ScriptedResponse::text(r#"{"headline": "Rain", "items": ["Umbrella"]}"#),
+        ]);
+        let config = AgentLoopConfig {
+            response_schema: Some(ResponseSchema::for_type::<Briefing>("briefing")),
+            ..Default::default()
+        };
+
+        let messages = run_loop(
+            &backend,
+            &config,
+            vec![genai::chat::ChatMessage::user("Brief me")],
+        )
+        .await
+        .0
+        .expect("loop completes");
+        let answer = messages[5].content.first_text().unwrap_or_default();
+        let value: serde_json::Value = serde_json::from_str(answer).expect("answer is JSON");
+        assert_eq!(value["items"][0], "Umbrella");
+        assert_eq!(messages.len(), 6);
+        let retry = messages[4].content.first_text().unwrap_or_default();
+        assert!(
+            retry.contains("invalid_response") && retry.contains("items"),
+            "{retry}"
+        );
+    }
+
+    #[tokio::test]
+    async fn answers_still_malformed_after_the_retries_fail_the_loop() {
+        let backend = ScriptedBackend::new([
+            ScriptedResponse::text("Rain."),
+            ScriptedResponse::text("Rain!"),
+        ]);
+        let config = AgentLoopConfig {
+            response_schema: Some(ResponseSchema {
+                max_retries: 0,
+                ..ResponseSchema::for_type::<Briefing>("briefing")
+            }),
+            ..Default::default()
+        };
+
+        let error = run_loop(
+            &backend,
+            &config,
+            vec![genai::chat::ChatMessage::user("Brief me")],
+        )
+        .await
+        .0
+        .expect_err("answer stays malformed");
+        assert!(error.to_string().contains("response schema"), "{error}");
+    }
+}
@@ -0,0 +1,259 @@
+use crate::agent_loop::{AgentEvent, AgentLoopConfig, AgentTool, agent_loop};
+use crate::backend::LlmBackend;
+
+/// Time a sub-agent gets to finish its task when no other timeout is set.
+const DEFAULT_SUBAGENT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(600);
+
+/// A tool that hands a task to a child agent loop and returns only its final answer.
+///
+/// The child starts from a fresh context with its own system prompt, tools and turn limit, all
+/// taken from `config`, so the intermediate tool results never reach the parent's context.
+/// Progress of the child is forwarded to the parent as `ToolExecutionUpdate` events, and the
+/// `Usage` events of the child as they are, so the tokens of the child count in the parent's
+/// totals.
+///
+/// The sub-agent is read-only when all of its tools are. Otherwise the parent approves the
+/// delegated task as a whole, the child asks its own `approval` callback, if it has one, before
+/// each mutating call.
+///
+/// The child does not inherit anything from the loop calling it: it sends its requests with
+/// its own client, a default `genai::Client` unless set with [`SubAgentTool::with_client`], and
+/// runs only the hooks given with [`SubAgentTool::with_hooks`]. Pass the parent's hooks there so
+/// redaction and auditing also cover the child.
+pub struct SubAgentTool {
+    name: String,
+    description: String,
+    client: std::sync::Arc<dyn LlmBackend>,
+    config: AgentLoopConfig,
+    timeout: std::time::Duration,
+}
+
+impl SubAgentTool {
+    pub fn new(name: &str, description: &str, config: AgentLoopConfig) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            client: std::sync::Arc::new(genai::Client::default()),
+            config,
+            timeout: DEFAULT_SUBAGENT_TIMEOUT,
+        }
+    }
+
+    pub fn with_client(mut self, client: impl LlmBackend + 'static) -> Self {
+        self.client = std::sync::Arc::new(client);
+        self
+    }
+
+    /// Hooks of the child loop, usually the ones of the parent loop. Replaces the hooks of
+    /// the config.
+    pub fn with_hooks(mut self, hooks: Vec<std::sync::Arc<dyn crate::AgentHook>>) -> Self {
+        self.config.hooks = hooks;
+        self
+    }
+
+    /// Time the whole child loop may take, tool calls and retries included.
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl AgentTool for SubAgentTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn definition(&self) -> genai::chat::Tool {
+        genai::chat::Tool::new(self.name.as_str())
+            .with_description(self.description.as_str())
+            .with_schema(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "task": {
+                        "type": "string",
+                        "description": "The task to delegate, with every detail the sub-agent needs: it does not see this conversation"
+                    }
+                },
+                "required": ["task"]
+            }))
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.config.tools.iter().all(|tool| tool.is_read_only())
+    }
+
+    fn timeout(&self) -> Option<std::time::Duration> {
+        Some(self.timeout)
+    }
+
+    async fn execute(
+        &self,
+        call_id: &str,
+        arguments: serde_json::Value,
+        event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+        cancel: &tokio_util::sync::CancellationToken,
+    ) -> anyhow::Result<String> {
+        let task = arguments
+            .get("task")
+            .and_then(|task| task.as_str())
+            .filter(|task| !task.trim().is_empty())
+            .ok_or_else(|| anyhow::anyhow!("Missing 'task' argument"))?;
+
+        let (child_tx, mut child_rx) = tokio::sync::mpsc::unbounded_channel();
+        let child = agent_loop(
+            self.client.as_ref(),
+            &self.config,
+            vec![genai::chat::ChatMessage::user(task)],
+            child_tx,
+            cancel.child_token(),
+            None,
+            None,
+        );
+        let forward = async {
+            while let Some(event) = child_rx.recv().await {
+                if let AgentEvent::Usage { .. } = event {
+                    let _ = event_tx.send(event);
+                } else if let Some(data) = progress_update(&self.name, &event) {
+                    let _ = event_tx.send(AgentEvent::ToolExecutionUpdate {
+                        call_id: call_id.to_string(),
+                        data,
+                    });
+                }
+            }
+        };
+        let (messages, ()) = tokio::join!(child, forward);
+
+        final_answer(&messages?)
+            .ok_or_else(|| anyhow::anyhow!("Sub-agent '{}' finished without an answer", self.name))
+    }
+}
+
+/// The child events worth showing to the parent, as `ToolExecutionUpdate` data.
+fn progress_update(subagent: &str, event: &AgentEvent) -> Option<serde_json::Value> {
+    let mut update = match event {
+        AgentEvent::TurnStart { turn_index } => serde_json::json!({
+            "event": "turn_start",
+            "turn_index": turn_index,
+        }),
+        AgentEvent::ToolExecutionStart {
+            tool_name,
+            arguments,
+            ..
+        } => serde_json::json!({
+            "event": "tool_start",
+            "tool_name": tool_name,
+            "arguments": arguments,
+        }),
+        AgentEvent::ToolExecutionEnd {
+            tool_name,
+            is_error,
+            ..
+        } => serde_json::json!({
+            "event": "tool_end",
+            "tool_name": tool_name,
+            "is_error": is_error,
+        }),
+        _ => return None,
+    };
+    update["subagent"] = serde_json::Value::from(subagent);
+    Some(update)
+}
+
+/// Text of the last assistant message, which is the child's answer once its loop ends.
+fn final_answer(messages: &[genai::chat::ChatMessage]) -> Option<String> {
+    messages
+        .iter()
+        .rev()
+        .find(|m| m.role == genai::chat::ChatRole::Assistant)
+        .map(|m| m.content.joined_texts().unwrap_or_default())
+        .filter(|answer| !answer.trim().is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn final_answer_is_the_last_assistant_text() {
+        let messages = vec![
+            genai::chat::ChatMessage::user("summarize"),
+            genai::chat::ChatMessage::assistant("looking"),
+            genai::chat::ChatMessage::user("more"),
+            genai::chat::ChatMessage::assistant("the summary"),
+        ];
+        assert_eq!(final_answer(&messages).as_deref(), Some("the summary"));
+        assert_eq!(final_answer(&messages[..1]), None);
+    }
+
+    #[test]
+    fn only_progress_events_are_forwarded() {
+        let update = progress_update(
+            "digest",
+            &AgentEvent::ToolExecutionStart {
+                call_id: String::from("call_1"),
+                tool_name: String::from("engineering_feed"),
+                arguments: serde_json::json!({ "source": "github" }),
+            },
+        )
+        .expect("tool starts are forwarded");
+        assert_eq!(update["subagent"], "digest");
+        assert_eq!(update["event"], "tool_start");
+        assert_eq!(update["arguments"]["source"], "github");
+
+        assert!(
+            progress_update(
+                "digest",
+                &AgentEvent::MessageDelta {
+                    text: String::from("partial")
+                }
+            )
+            .is_none()
+        );
+    }
+
+    /// Counts the requests it sees.
+    struct CountingHook(std::sync::atomic::AtomicUsize);
+
+    #[async_trait::async_trait]
+    impl crate::AgentHook for CountingHook {
+        async fn before_llm_call(
+            &self,
+            _model: &str,
+            _request: &mut genai::chat::ChatRequest,
+        ) -> anyhow::Result<()> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn child_runs_the_hooks_it_is_given() {
+        let hook = std::sync::Arc::new(CountingHook(std::sync::atomic::AtomicUsize::new(0)));
+        let tool = SubAgentTool::new("digest", "Digest", AgentLoopConfig::default())
+            .with_client(crate::ScriptedBackend::new([
+                crate::ScriptedResponse::text("the digest").with_usage(120, 30),
+            ]))
+            .with_hooks(vec![hook.clone()]);
+        let (event_tx, mut event_rx) = tokio::sync::mpsc::unbounded_channel();
+        let answer = tool
+            .execute(
+                "call_1",
+                serde_json::json!({ "task": "summarize" }),
+                &event_tx,
+                &tokio_util::sync::CancellationToken::new(),
+            )
+            .await
+            .expect("sub-agent answers");
+        assert_eq!(answer, "the digest");
+        assert_eq!(hook.0.load(std::sync::atomic::Ordering::SeqCst), 1);
+        drop(event_tx);
+        let mut usage = Vec::new();
+        while let Some(event) = event_rx.recv().await {
+            if let AgentEvent::Usage { usage: tokens, .. } = event {
+                usage.push(tokens.completion_tokens);
+            }
+        }
+        assert_eq!(usage, [30], "the child's usage reaches the parent");
+    }
+}
@@ -0,0 +1,143 @@
+//! Fixtures shared by the tests of this crate and, through the `test-support` feature, of the
+//! crates that use it.
+
+use crate::{AgentEvent, AgentLoopConfig, AgentTool, LlmBackend, SessionFile};
+use genai::chat::{ChatMessage, ToolCall, ToolResponse};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A `gpt-4o` session holding `messages`, created and updated now.
+pub fn session_file(id: &str, messages: Vec<ChatMessage>) -> SessionFile {
+    let now = chrono::Utc::now();
+    SessionFile {
+        id: id.to_string(),
+        model: String::from("gpt-4o"),
+        created_at: now,
+        updated_at: now,
+        messages,
+        parent_id: None,
+        forked_at: None,
+        usage: Default::default(),
+    }
+}
+
+/// Five messages: a question answered through a `weather_forecast` tool call, then a follow-up
+/// question. Message 2 is the tool response.
+pub fn weather_conversation() -> Vec<ChatMessage> {
+    vec![
+        ChatMessage::user("\nWhat's the weather like?\nIn Brooklyn"),
+        ChatMessage::from(vec![ToolCall {
+            call_id: String::from("call_1"),
+            fn_name: String::from("weather_forecast"),
+            fn_arguments: serde_json::json!({ "mode": "current" }),
+            thought_signatures: None,
+        }]),
+        ChatMessage::from(ToolResponse::new("call_1", "{\"temperature\":\"72°F\"}")),
+        ChatMessage::assistant("It is 72°F and sunny."),
+        ChatMessage::user("And tomorrow?"),
+    ]
+}
+
+/// A fresh directory under the system temp dir, removed with its content when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(prefix: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("{prefix}-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).expect("temp dir is created");
+        Self(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Echoes its arguments back as its result, and counts its runs in `runs`.
+pub struct EchoTool {
+    pub name: &'static str,
+    pub read_only: bool,
+    /// Schema the arguments are checked against, none to take any arguments.
+    pub schema: Option<serde_json::Value>,
+    pub runs: Arc<AtomicUsize>,
+}
+
+impl EchoTool {
+    /// A mutating tool called `name`, taking any arguments.
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            read_only: false,
+            schema: None,
+            runs: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl AgentTool for EchoTool {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn definition(&self) -> genai::chat::Tool {
+        let tool = genai::chat::Tool::new(self.name);
+        match &self.schema {
+            Some(schema) => tool.with_schema(schema.clone()),
+            None => tool,
+        }
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    async fn execute(
+        &self,
+        _call_id: &str,
+        arguments: serde_json::Value,
+        _event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+        _cancel: &tokio_util::sync::CancellationToken,
+    ) -> anyhow::Result<String> {
+        self.runs.fetch_add(1, Ordering::SeqCst);
+        Ok(arguments.to_string())
+    }
+}
+
+/// Runs [`crate::agent_loop`] on `backend` from `messages`, and collects the events it emitted.
+pub async fn run_loop(
+    backend: &dyn LlmBackend,
+    config: &AgentLoopConfig,
+    messages: Vec<ChatMessage>,
+) -> (anyhow::Result<Vec<ChatMessage>>, Vec<AgentEvent>) {
+    run_loop_cancelled_by(
+        backend,
+        config,
+        messages,
+        tokio_util::sync::CancellationToken::new(),
+    )
+    .await
+}
+
+/// [`run_loop`], cancelled through `cancel`.
+pub async fn run_loop_cancelled_by(
+    backend: &dyn LlmBackend,
+    config: &AgentLoopConfig,
+    messages: Vec<ChatMessage>,
+    cancel: tokio_util::sync::CancellationToken,
+) -> (anyhow::Result<Vec<ChatMessage>>, Vec<AgentEvent>) {
+    let (event_tx, mut event_rx) = tokio::sync::mpsc::unbounded_channel();
+    let result = crate::agent_loop(backend, config, messages, event_tx, cancel, None, None).await;
+    let mut events = Vec::new();
+    while let Some(event) = event_rx.recv().await {
+        events.push(event);
+    }
+    (result, events)
+}
@@ -0,0 +1,314 @@
+use crate::agent_loop::{AgentEvent, AgentTool};
+use std::path::PathBuf;
+
+/// Name of the built-in tool reading spilled tool outputs.
+pub const READ_TOOL_OUTPUT: &str = "read_tool_output";
+
+/// Characters returned by `read_tool_output` when the model does not ask for a `limit`.
+const DEFAULT_PAGE_CHARS: usize = 10_000;
+
+/// Largest page `read_tool_output` returns, whatever `limit` the model asks for.
+const MAX_PAGE_CHARS: usize = 50_000;
+
+/// Budget of the tool results sent to the LLM.
+#[derive(Debug, Clone)]
+pub struct ToolOutputConfig {
+    /// Maximum length, in characters, of a tool result. Tools can set their own budget with
+    /// `AgentTool::max_output_chars`. `None` keeps every result whole.
+    pub max_chars: Option<usize>,
+    /// What happens to the results over budget.
+    pub overflow: ToolOutputOverflow,
+}
+
+impl Default for ToolOutputConfig {
+    fn default() -> Self {
+        Self {
+            max_chars: Some(50_000),
+            overflow: ToolOutputOverflow::Truncate,
+        }
+    }
+}
+
+/// What happens to a tool result longer than its budget.
+#[derive(Debug, Clone)]
+pub enum ToolOutputOverflow {
+    /// Keep the beginning of the result, followed by a notice saying how much was cut.
+    Truncate,
+    /// Keep the beginning of the result and save the whole of it in the store. The model gets
+    /// a handle to page through it with the built-in `read_tool_output` tool.
+    Spill(ToolOutputStore),
+}
+
+/// Directory holding the full results of the tool calls that went over budget, one file per
+/// call. It is also the `read_tool_output` tool the loop adds when results are spilled.
+#[derive(Debug, Clone)]
+pub struct ToolOutputStore {
+    dir: PathBuf,
+}
+
+impl ToolOutputStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Saves `content` under a new handle and returns it. The handle is the call id, without
+    /// the characters a file name cannot hold, numbered when an earlier output took it: some
+    /// providers reuse call ids on every turn.
+    fn save(&self, call_id: &str, content: &str) -> anyhow::Result<String> {
+        use std::io::Write;
+
+        let mut base: String = call_id
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+            .collect();
+        if base.is_empty() {
+            base = String::from("output");
+        }
+        std::fs::create_dir_all(&self.dir)?;
+        let mut handle = base.clone();
+        let mut number = 1;
+        loop {
+            let file = std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(self.path(&handle)?);
+            match file {
+                Ok(mut file) => {
+                    file.write_all(content.as_bytes())?;
+                    return Ok(handle);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    number += 1;
+                    handle = format!("{base}-{number}");
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn path(&self, handle: &str) -> anyhow::Result<PathBuf> {
+        anyhow::ensure!(
+            !handle.is_empty()
+                && handle
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_')),
+            "Invalid tool output handle '{handle}'"
+        );
+        Ok(self.dir.join(format!("{handle}.txt")))
+    }
+}
+
+#[async_trait::async_trait]
+impl AgentTool for ToolOutputStore {
+    fn name(&self) -> &str {
+        READ_TOOL_OUTPUT
+    }
+
+    fn definition(&self) -> genai::chat::Tool {
+        genai::chat::Tool::new(READ_TOOL_OUTPUT)
+            .with_description(
+                "Read part of a tool result that was too long to be returned whole. The \
+                 truncated result gives the handle and the offset to continue from.",
+            )
+            .with_schema(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "handle": {
+                        "type": "string",
+                        "description": "Handle of the saved output"
+                    },
+                    "offset": {
+                        "type": "integer",
+                        "minimum": 0,
+                        "description": "Character to start reading from (default: 0)"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "maximum": MAX_PAGE_CHARS,
+                        "description": format!("Number of characters to read (default: {DEFAULT_PAGE_CHARS})")
+                    }
+                },
+                "required": ["handle"]
+            }))
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    fn max_output_chars(&self) -> Option<usize> {
+        // Pages are bounded by `limit` already, they must never be spilled again
+        Some(usize::MAX)
+    }
+
+    async fn execute(
+        &self,
+        _call_id: &str,
+        arguments: serde_json::Value,
+        _event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+        _cancel: &tokio_util::sync::CancellationToken,
+    ) -> anyhow::Result<String> {
+        let handle = arguments
+            .get("handle")
+            .and_then(|handle| handle.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Missing 'handle' argument"))?;
+        let offset = arguments
+            .get("offset")
+            .and_then(|offset| offset.as_u64())
+            .unwrap_or(0) as usize;
+        let limit = arguments
+            .get("limit")
+            .and_then(|limit| limit.as_u64())
+            .map_or(DEFAULT_PAGE_CHARS, |limit| limit as usize)
+            .clamp(1, MAX_PAGE_CHARS);
+
+        let content = std::fs::read_to_string(self.path(handle)?)
+            .map_err(|e| anyhow::anyhow!("No saved output with handle '{handle}' ({e})"))?;
+        let total_chars = content.chars().count();
+        let page: String = content.chars().skip(offset).take(limit).collect();
+        let end = offset.saturating_add(page.chars().count());
+
+        Ok(serde_json::json!({
+            "handle": handle,
+            "offset": offset,
+            "total_chars": total_chars,
+            "next_offset": (end < total_chars).then_some(end),
+            "content": page,
+        })
+        .to_string())
+    }
+}
+
+/// Cuts `content` down to `max_chars` following `overflow`, with a notice telling the model
+/// what it is missing.
+pub(crate) fn limit_output(
+    content: String,
+    max_chars: Option<usize>,
+    call_id: &str,
+    overflow: &ToolOutputOverflow,
+) -> String {
+    let Some(max_chars) = max_chars else {
+        return content;
+    };
+    let total_chars = content.chars().count();
+    if total_chars <= max_chars {
+        return content;
+    }
+
+    let mut limited: String = content.chars().take(max_chars).collect();
+    let spilled = match overflow {
+        ToolOutputOverflow::Truncate => None,
+        ToolOutputOverflow::Spill(store) => match store.save(call_id, &content) {
+            Ok(handle) => Some(handle),
+            Err(e) => {
+                log::warn!(
+                    "Failed to save the output of tool call '{call_id}', truncating it: {e}"
+                );
+                None
+            }
+        },
+    };
+    match spilled {
+        Some(handle) => limited.push_str(&format!(
+            "\n\n[Output truncated: {max_chars} of {total_chars} characters shown. The full \
+             output is saved with handle \"{handle}\", call {READ_TOOL_OUTPUT} with offset \
+             {max_chars} to read the rest.]"
+        )),
+        None => limited.push_str(&format!(
+            "\n\n[Output truncated: {max_chars} of {total_chars} characters shown. Narrow the \
+             request (filters, limits) to see the rest.]"
+        )),
+    }
+    limited
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_outputs_are_kept_whole() {
+        let content = String::from("short");
+        assert_eq!(
+            limit_output(content, Some(10), "call_1", &ToolOutputOverflow::Truncate),
+            "short"
+        );
+    }
+
+    #[test]
+    fn long_outputs_are_truncated_with_a_notice() {
+        let limited = limit_output(
+            "é".repeat(100),
+            Some(10),
+            "call_1",
+            &ToolOutputOverflow::Truncate,
+        );
+        assert!(limited.starts_with(&"é".repeat(10)));
+        assert!(limited.contains("10 of 100 characters shown"));
+    }
+
+    #[tokio::test]
+    async fn spilled_outputs_are_read_back_in_pages() {
+        let dir = crate::test_support::TempDir::new("tool-output");
+        let store = ToolOutputStore::new(dir.path());
+        let content: String = (0..100)
+            .map(|i| char::from(b'a' + (i % 26) as u8))
+            .collect();
+
+        let limited = limit_output(
+            content.clone(),
+            Some(40),
+            "call/../1",
+            &ToolOutputOverflow::Spill(store.clone()),
+        );
+        assert!(limited.contains("handle \"call1\""));
+
+        let (event_tx, _event_rx) = tokio::sync::mpsc::unbounded_channel();
+        let cancel = tokio_util::sync::CancellationToken::new();
+        let page = store
+            .execute(
+                "call_2",
+                serde_json::json!({ "handle": "call1", "offset": 40, "limit": 50 }),
+                &event_tx,
+                &cancel,
+            )
+            .await
+            .expect("saved output is readable");
+        let page: serde_json::Value = serde_json::from_str(&page).expect("page is JSON");
+        assert_eq!(page["content"], content[40..90]);
+        assert_eq!(page["next_offset"], 90);
+        assert_eq!(page["total_chars"], 100);
+
+        assert!(
+            store
+                .execute(
+                    "call_3",
+                    serde_json::json!({ "handle": "../secrets" }),
+                    &event_tx,
+                    &cancel,
+                )
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn reused_and_colliding_call_ids_get_their_own_handles() {
+        let dir = crate::test_support::TempDir::new("tool-output");
+        let store = ToolOutputStore::new(dir.path());
+        let handles: Vec<String> = [
+            ("call_0", "first"),
+            ("call_0", "second"),
+            ("call1", "third"),
+            ("call/1", "fourth"),
+        ]
+        .into_iter()
+        .map(|(call_id, content)| store.save(call_id, content).expect("output is saved"))
+        .collect();
+        assert_eq!(handles, ["call_0", "call_0-2", "call1", "call1-2"]);
+        let first = std::fs::read_to_string(store.path("call_0").expect("valid handle"))
+            .expect("first output is kept");
+        assert_eq!(first, "first");
+    }
+}
@@ -0,0 +1,260 @@
+use crate::agent_loop::{AgentEvent, AgentTool};
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+
+/// Schemas derived by [`schema_for`] for the [`TypedTool`] arguments, by schema id.
+static DERIVED_SCHEMAS: LazyLock<Mutex<HashMap<String, serde_json::Value>>> =
+    LazyLock::new(Default::default);
+
+/// Compiled tool schemas by tool name, with the schema each was compiled from.
+static VALIDATORS: LazyLock<Mutex<HashMap<String, CompiledSchema>>> =
+    LazyLock::new(Default::default);
+
+type CompiledSchema = (serde_json::Value, Arc<jsonschema::Validator>);
+
+/// A tool whose arguments are a serde struct. The JSON schema sent to the LLM is derived from
+/// `Args` and the arguments are parsed into it before `run` is called, so the tool only deals
+/// with well-formed input. Doc comments on the fields become their descriptions in the schema.
+///
+/// Every `TypedTool` is an [`AgentTool`].
+#[async_trait::async_trait]
+pub trait TypedTool: Send + Sync {
+    type Args: serde::de::DeserializeOwned + schemars::JsonSchema + Send;
+
+    /// The tool name, sent to the LLM.
+    fn name(&self) -> &str;
+
+    /// What the tool does, sent to the LLM.
+    fn description(&self) -> &str;
+
+    /// See [`AgentTool::is_read_only`].
+    fn is_read_only(&self) -> bool {
+        false
+    }
+
+    /// See [`AgentTool::timeout`].
+    fn timeout(&self) -> Option<std::time::Duration> {
+        None
+    }
+
+    /// See [`AgentTool::max_output_chars`].
+    fn max_output_chars(&self) -> Option<usize> {
+        None
+    }
+
+    /// Execute the tool with its parsed arguments. See [`AgentTool::execute`].
+    async fn run(
+        &self,
+        call_id: &str,
+        args: Self::Args,
+        event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+        cancel: &tokio_util::sync::CancellationToken,
+    ) -> anyhow::Result<String>;
+}
+
+#[async_trait::async_trait]
+impl<T: TypedTool> AgentTool for T {
+    fn name(&self) -> &str {
+        TypedTool::name(self)
+    }
+
+    fn definition(&self) -> genai::chat::Tool {
+        genai::chat::Tool::new(TypedTool::name(self))
+            .with_description(self.description())
+            .with_schema(derived_schema::<T::Args>())
+    }
+
+    fn is_read_only(&self) -> bool {
+        TypedTool::is_read_only(self)
+    }
+
+    fn timeout(&self) -> Option<std::time::Duration> {
+        TypedTool::timeout(self)
+    }
+
+    fn max_output_chars(&self) -> Option<usize> {
+        TypedTool::max_output_chars(self)
+    }
+
+    async fn execute(
+        &self,
+        call_id: &str,
+        arguments: serde_json::Value,
+        event_tx: &tokio::sync::mpsc::UnboundedSender<AgentEvent>,
+        cancel: &tokio_util::sync::CancellationToken,
+    ) -> anyhow::Result<String> {
+        let args: T::Args = serde_json::from_value(arguments)?;
+        self.run(call_id, args, event_tx, cancel).await
+    }
+}
+
+/// JSON schema of `T` as tool parameters: subschemas inlined, without the `$schema` and
+/// `title` keywords the providers do not expect.
+pub fn schema_for<T: schemars::JsonSchema>() -> serde_json::Value {
+    let mut settings = schemars::generate::SchemaSettings::default();
+    settings.meta_schema = None;
+    settings.inline_subschemas = true;
+    let mut schema = settings.into_generator().root_schema_for::<T>().to_value();
+    if let Some(schema) = schema.as_object_mut() {
+        schema.remove("title");
+    }
+    schema
+}
+
+/// [`schema_for`] of `T`, derived once.
+fn derived_schema<T: schemars::JsonSchema>() -> serde_json::Value {
+    let id = T::schema_id();
+    let mut schemas = DERIVED_SCHEMAS
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    if let Some(schema) = schemas.get(id.as_ref()) {
+        return schema.clone();
+    }
+    let schema = schema_for::<T>();
+    schemas.insert(id.into_owned(), schema.clone());
+    schema
+}
+
+/// The compiled schema of `definition`, `None` for tools without one. Compiled once per
+/// tool, and again only when its schema changes.
+fn validator(definition: &genai::chat::Tool) -> anyhow::Result<Option<Arc<jsonschema::Validator>>> {
+    let Some(schema) = definition.schema.as_ref() else {
+        return Ok(None);
+    };
+    let mut validators = VALIDATORS
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    if let Some((compiled_from, validator)) = validators.get(definition.name.as_str())
+        && compiled_from == schema
+    {
+        return Ok(Some(validator.clone()));
+    }
+    let validator =
+        Arc::new(jsonschema::validator_for(schema).map_err(|e| {
+            anyhow::anyhow!("Schema of tool '{}' is invalid: {e}", definition.name)
+        })?);
+    validators.insert(
+        definition.name.to_string(),
+        (schema.clone(), validator.clone()),
+    );
+    Ok(Some(validator))
+}
+
+/// Fails when the schema of `definition` does not compile. A tool with such a schema cannot
+/// be called, see [`crate::AgentLoopConfig::tools`].
+pub fn check_tool_schema(definition: &genai::chat::Tool) -> anyhow::Result<()> {
+    validator(definition).map(|_| ())
+}
+
+/// Checks `arguments` against the schema of `definition`. Violations come back as the
+/// structured error sent to the LLM in place of the tool result.
+///
+/// Tools without a schema are not checked. Tools whose schema does not compile are not
+/// called, the error says so.
+pub(crate) fn validate_arguments(
+    definition: &genai::chat::Tool,
+    arguments: &serde_json::Value,
+) -> Result<(), String> {
+    let validator = match validator(definition) {
+        Ok(Some(validator)) => validator,
+        Ok(None) => return Ok(()),
+        Err(e) => {
+            log::error!("{e}");
+            return Err(serde_json::json!({
+                "error": "invalid_schema",
+                "message": format!("{e}. The tool cannot be called."),
+            })
+            .to_string());
+        }
+    };
+
+    let violations = schema_violations(&validator, arguments);
+    if violations.is_empty() {
+        return Ok(());
+    }
+
+    Err(serde_json::json!({
+        "error": "invalid_arguments",
+        "message": format!(
+            "The arguments do not match the schema of tool '{}', the tool was not called. Fix them and call it again.",
+            definition.name
+        ),
+        "violations": violations,
+    })
+    .to_string())
+}
+
+/// Where and how `value` breaks the schema of `validator`, as `{ field, message }` objects.
+pub(crate) fn schema_violations(
+    validator: &jsonschema::Validator,
+    value: &serde_json::Value,
+) -> Vec<serde_json::Value> {
+    validator
+        .iter_errors(value)
+        .map(|error| {
+            serde_json::json!({
+                "field": match error.instance_path().as_str() {
+                    "" => "/",
+                    path => path,
+                },
+                "message": error.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(serde::Deserialize, schemars::JsonSchema)]
+    #[allow(dead_code)]
+    struct FeedArgs {
+        /// The feed to read
+        source: String,
+        /// Maximum number of items
+        limit: Option<u32>,
+    }
+
+    #[test]
+    fn schemas_are_derived_from_the_arguments_struct() {
+        let schema = schema_for::<FeedArgs>();
+        assert_eq!(schema["type"], "object");
+        assert_eq!(schema["required"], serde_json::json!(["source"]));
+        assert_eq!(
+            schema["properties"]["source"]["description"],
+            "The feed to read"
+        );
+        assert!(schema.get("$schema").is_none() && schema.get("title").is_none());
+    }
+
+    #[test]
+    fn tools_with_an_invalid_schema_are_not_called() {
+        let tool = genai::chat::Tool::new("broken")
+            .with_schema(serde_json::json!({ "type": "object", "minProperties": "one" }));
+        assert!(check_tool_schema(&tool).is_err());
+        let error = validate_arguments(&tool, &serde_json::json!({})).expect_err("call fails");
+        assert!(error.contains("invalid_schema"), "{error}");
+    }
+
+    #[test]
+    fn violations_list_the_offending_fields() {
+        let tool = genai::chat::Tool::new("engineering_feed").with_schema(schema_for::<FeedArgs>());
+        assert!(validate_arguments(&tool, &serde_json::json!({ "source": "github" })).is_ok());
+
+        let error = validate_arguments(&tool, &serde_json::json!({ "limit": "ten" }))
+            .expect_err("arguments are invalid");
+        let error: serde_json::Value = serde_json::from_str(&error).expect("error is JSON");
+        assert_eq!(error["error"], "invalid_arguments");
+        let fields: Vec<&str> = error["violations"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|v| v["field"].as_str())
+            .collect();
+        assert!(
+            fields.contains(&"/") && fields.contains(&"/limit"),
+            "{fields:?}"
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use config::ModelPrice;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Tokens consumed by one or more LLM requests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    /// Input tokens, cached ones included.
+    pub prompt_tokens: u64,
+    /// Output tokens, reasoning ones included.
+    pub completion_tokens: u64,
+    /// Input tokens read from the provider's prompt cache.
+    pub cached_tokens: u64,
+}
+
+impl TokenUsage {
+    /// Cost in dollars of these tokens at `price`.
+    pub fn cost(&self, price: &ModelPrice) -> f64 {
+        let cached = self.cached_tokens.min(self.prompt_tokens);
+        let uncached = self.prompt_tokens - cached;
+        let cached_price = price
+            .cached_input_per_million
+            .unwrap_or(price.input_per_million);
+        (uncached as f64 * price.input_per_million
+            + cached as f64 * cached_price
+            + self.completion_tokens as f64 * price.output_per_million)
+            / 1_000_000.0
+    }
+}
+
+impl From<&genai::chat::Usage> for TokenUsage {
+    fn from(usage: &genai::chat::Usage) -> Self {
+        let count = |tokens: Option<i32>| tokens.map_or(0, |t| t.max(0) as u64);
+        Self {
+            prompt_tokens: count(usage.prompt_tokens),
+            completion_tokens: count(usage.completion_tokens),
+            cached_tokens: count(
+                usage
+                    .prompt_tokens_details
+                    .as_ref()
+                    .and_then(|d| d.cached_tokens),
+            ),
+        }
+    }
+}
+
+impl std::ops::AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.cached_tokens += other.cached_tokens;
+    }
+}
+
+impl std::iter::Sum for TokenUsage {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |mut total, usage| {
+            total += usage;
+            total
+        })
+    }
+}
+
+/// Cost in dollars of usage recorded per model. `None` when a model has no price in `pricing`.
+pub fn usage_cost(
+    usage: &BTreeMap<String, TokenUsage>,
+    pricing: &HashMap<String, ModelPrice>,
+) -> Option<f64> {
+    usage
+        .iter()
+        .map(|(model, usage)| pricing.get(model).map(|price| usage.cost(price)))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cached_tokens_use_the_cached_price() {
+        let usage = TokenUsage {
+            prompt_tokens: 1_000_000,
+            completion_tokens: 100_000,
+            cached_tokens: 400_000,
+        };
+        let price = ModelPrice {
+            input_per_million: 2.5,
+            output_per_million: 10.0,
+            cached_input_per_million: Some(1.25),
+        };
+        // 600k uncached at 2.5, 400k cached at 1.25, 100k output at 10
+        assert!((usage.cost(&price) - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unpriced_models_have_no_cost() {
+        let usage = BTreeMap::from([
+            (
+                String::from("gpt-4o"),
+                TokenUsage {
+                    prompt_tokens: 1_000_000,
+                    ..Default::default()
+                },
+            ),
+            (String::from("local-model"), TokenUsage::default()),
+        ]);
+        let mut pricing = HashMap::from([(
+            String::from("gpt-4o"),
+            ModelPrice {
+                input_per_million: 2.5,
+                output_per_million: 10.0,
+                cached_input_per_million: None,
+            },
+        )]);
+        assert_eq!(usage_cost(&usage, &pricing), None);
+
+        pricing.insert(
+            String::from("local-model"),
+            ModelPrice {
+                input_per_million: 0.0,
+                output_per_million: 0.0,
+                cached_input_per_million: None,
+            },
+        );
+        assert_eq!(usage_cost(&usage, &pricing), Some(2.5));
+    }
+}
@@ -0,0 +1,761 @@
+use agent_core::{
+    AgentEvent, AgentLoopConfig, AgentTool, ApprovalFn, BpeTokenCounter, Cassette, CassettePlayer,
+    CassetteRecorder, CompactionConfig, LlmBackend, Session, TokenUsage, ToolApproval,
+    ToolOutputConfig, ToolOutputOverflow, ToolOutputStore, agent_loop, open_session_store,
+    usage_cost,
+};
+use anyhow::{Result, anyhow};
+use clap::{Args, Parser};
+use config::{AgentProfile, Config, ModelPrice, Profile};
+use genai::chat::ChatMessage;
+use log::warn;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
+
+const CHAT_SYSTEM_PROMPT: &str = "
+You are the Daily Bugle, a personal assistant that runs in the user's terminal.
+
+Answer concisely and prefer plain text over heavy markdown, the output is rendered in a terminal.
+When a tool can answer the question, call it instead of guessing.
+";
+
+/// Options of the interactive chat, shared by `chat` and `session resume`.
+#[derive(Debug, Clone, Default, Args)]
+pub struct ChatSettings {
+    #[clap(
+        short,
+        long,
+        help = "Model used for the conversation (defaults to the agent's model, then the \
+                session's model)"
+    )]
+    pub model: Option<String>,
+    #[clap(
+        short,
+        long,
+        value_name = "NAME",
+        help = "Agent declared as [[agent]] in the config file, setting the system prompt, \
+                model and tools of the chat"
+    )]
+    pub agent: Option<String>,
+    #[clap(
+        long,
+        help = "Print the reasoning of reasoning models while they think"
+    )]
+    pub show_reasoning: bool,
+    #[clap(
+        long,
+        value_name = "PATH",
+        help = "Record the LLM responses, tool results and approvals to a cassette file, \
+                to replay the chat with `session replay`"
+    )]
+    pub record: Option<PathBuf>,
+}
+
+impl ChatSettings {
+    /// The `[[agent]]` picked with `--agent`, if any.
+    fn agent_profile<'a>(&self, config: &'a Config) -> Result<Option<&'a AgentProfile>> {
+        self.agent
+            .as_deref()
+            .map(|name| config.agent(name))
+            .transpose()
+    }
+
+    /// The model picked with `--model`, or the one of the agent.
+    fn model(&self, agent: Option<&AgentProfile>) -> Option<String> {
+        self.model
+            .clone()
+            .or_else(|| agent.and_then(|agent| agent.model.clone()))
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct ChatArgs {
+    #[clap(flatten)]
+    pub settings: ChatSettings,
+    #[clap(short, long, help = "ID of a saved session to resume")]
+    pub session: Option<String>,
+}
+
+pub async fn handle_chat_command(
+    args: ChatArgs,
+    config: &Config,
+    profile: Option<&Profile>,
+) -> Result<()> {
+    let store = open_session_store(config.session_store)?;
+    let agent = args.settings.agent_profile(config)?;
+    let session = match args.session {
+        Some(id) => Session::load(store, &id)?,
+        None => Session::new(
+            store,
+            &args
+                .settings
+                .model(agent)
+                .unwrap_or_else(|| AgentLoopConfig::default().model),
+        )?,
+    };
+    run_chat(session, &args.settings, config, profile).await
+}
+
+/// Runs the interactive REPL on top of `session` until the user exits.
+///
+/// Every line typed by the user starts a new agent turn. Lines typed while a turn runs answer
+/// the pending approval question, if any, or steer the agent. The session is persisted after
+/// each message, so an interrupted turn keeps everything that was produced before the
+/// interruption.
+pub async fn run_chat(
+    mut session: Session,
+    settings: &ChatSettings,
+    config: &Config,
+    profile: Option<&Profile>,
+) -> Result<()> {
+    let agent = settings.agent_profile(config)?;
+    if let Some(model) = settings.model(agent) {
+        session.file.model = model;
+    }
+
+    let mut input = spawn_stdin_reader();
+    let (approval_tx, mut approval_rx) = unbounded_channel();
+    let mut tools = chat_tools(config, profile, &session.file.model, agent).await;
+    let mut approval = approval_prompt(approval_tx);
+    let recorder = settings
+        .record
+        .as_ref()
+        .map(|_| CassetteRecorder::default());
+    let client: Box<dyn LlmBackend> = match &recorder {
+        Some(recorder) => {
+            tools = recorder.tools(tools);
+            approval = recorder.approval(approval);
+            Box::new(recorder.backend(genai::Client::default()))
+        }
+        None => Box::new(genai::Client::default()),
+    };
+    let loop_config = chat_loop_config(&session, tools, approval, agent)?;
+
+    let agent_name = agent
+        .map(|agent| format!(", agent {}", agent.name))
+        .unwrap_or_default();
+    println!(
+        "Session {} ({}{agent_name}) - type /exit to quit, type during a turn to steer it, Ctrl-C cancels it",
+        session.file.id, session.file.model
+    );
+
+    let mut next_prompt = None;
+    loop {
+        let line = match next_prompt.take() {
+            Some(line) => line,
+            None => {
+                print!("> ");
+                std::io::stdout().flush()?;
+                tokio::select! {
+                    line = input.recv() => match line {
+                        Some(line) => line,
+                        None => break,
+                    },
+                    _ = tokio::signal::ctrl_c() => break,
+                }
+            }
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if matches!(line, "/exit" | "/quit") {
+            break;
+        }
+
+        let prompt = ChatMessage::user(line);
+        if let Some(recorder) = &recorder {
+            let mut initial_messages = session.file.messages.clone();
+            initial_messages.push(prompt.clone());
+            recorder.start_run(&initial_messages)?;
+        }
+        let input = TurnInput {
+            lines: &mut input,
+            approvals: &mut approval_rx,
+        };
+        (session, next_prompt) = run_turn(
+            client.as_ref(),
+            &loop_config,
+            &config.pricing,
+            settings.show_reasoning,
+            session,
+            prompt,
+            input,
+        )
+        .await?;
+        if let (Some(recorder), Some(path)) = (&recorder, &settings.record) {
+            recorder.save(path)?;
+        }
+    }
+
+    println!();
+    Ok(())
+}
+
+/// Replays the chat recorded in `cassette` on top of `session`, printing it as it happened,
+/// then tells whether it ends with the messages saved in the session.
+///
+/// The LLM responses, tool results and approvals come from the cassette. The built-in tools are
+/// still built, their definitions are part of the requests and must match the recorded ones, so
+/// the chat must be replayed with the `agent` it was recorded with. The MCP servers are not
+/// connected, their tools are played from the definitions in the cassette.
+///
+/// Large tool results are spilled to a temporary directory, removed after the replay, so the
+/// replay leaves the session's saved outputs alone.
+pub async fn replay_chat(
+    mut session: Session,
+    cassette: Cassette,
+    agent: Option<&AgentProfile>,
+    config: &Config,
+    profile: Option<&Profile>,
+) -> Result<()> {
+    // The model the chat was recorded with, the session may have switched since
+    if let Some(call) = cassette
+        .runs
+        .iter()
+        .flat_map(|run| run.llm_calls.first())
+        .next()
+    {
+        session.file.model = call.model.clone();
+    }
+    let player = CassettePlayer::new(cassette);
+    let tools = player.tools(agent_tools(
+        agent,
+        crate::tools::all_tools(config, profile, &session.file.model),
+    ));
+    let outputs_dir =
+        std::env::temp_dir().join(format!("daily-bugle-replay-{}", uuid::Uuid::new_v4()));
+    let mut loop_config = chat_loop_config(&session, tools, player.approval(), agent)?;
+    loop_config.tool_output.overflow =
+        ToolOutputOverflow::Spill(ToolOutputStore::new(&outputs_dir));
+    let replayed = replay_runs(&player, &loop_config).await;
+    if outputs_dir.exists() {
+        std::fs::remove_dir_all(&outputs_dir)?;
+    }
+    let messages = replayed?;
+
+    let replayed = serde_json::to_value(&messages)?;
+    let saved = serde_json::to_value(&session.file.messages)?;
+    if replayed == saved {
+        println!("Replay matches session {}", session.file.id);
+    } else {
+        let diverged_at = replayed
+            .as_array()
+            .zip(saved.as_array())
+            .and_then(|(replayed, saved)| replayed.iter().zip(saved).position(|(r, s)| r != s))
+            .unwrap_or(messages.len().min(session.file.messages.len()));
+        println!(
+            "Replay ends with {} messages, session {} has {}, they differ from message {diverged_at}",
+            messages.len(),
+            session.file.id,
+            session.file.messages.len()
+        );
+    }
+    Ok(())
+}
+
+/// Plays every run of the cassette, printing it, and returns the messages of the last one.
+async fn replay_runs(
+    player: &CassettePlayer,
+    loop_config: &AgentLoopConfig,
+) -> Result<Vec<ChatMessage>> {
+    let backend = player.backend();
+    let mut messages = Vec::new();
+    while let Some(initial_messages) = player.next_run()? {
+        if let Some(prompt) = initial_messages.last().and_then(|m| m.content.first_text()) {
+            println!("> {prompt}");
+        }
+        let (event_tx, event_rx) = unbounded_channel();
+        let printer = tokio::spawn(print_events(event_rx, false));
+        let result = agent_loop(
+            &backend,
+            loop_config,
+            initial_messages,
+            event_tx,
+            CancellationToken::new(),
+            None,
+            None,
+        )
+        .await;
+        printer.await?;
+        messages = result?;
+    }
+    player.finish()?;
+    Ok(messages)
+}
+
+/// The tools of the chat: the built-in ones and those of the configured MCP servers, limited
+/// to the tools the agent allows.
+async fn chat_tools(
+    config: &Config,
+    profile: Option<&Profile>,
+    model: &str,
+    agent: Option<&AgentProfile>,
+) -> Vec<Box<dyn AgentTool>> {
+    let tools =
+        crate::tools::with_mcp_tools(config, crate::tools::all_tools(config, profile, model)).await;
+    agent_tools(agent, tools)
+}
+
+/// The `tools` the agent allows, all of them without an agent or a `tools` list.
+fn agent_tools(
+    agent: Option<&AgentProfile>,
+    tools: Vec<Box<dyn AgentTool>>,
+) -> Vec<Box<dyn AgentTool>> {
+    match agent {
+        Some(agent) if agent.tools.is_some() => allowed_tools(agent, tools),
+        _ => tools,
+    }
+}
+
+/// The `tools` named in the `tools` list of the agent.
+fn allowed_tools(agent: &AgentProfile, tools: Vec<Box<dyn AgentTool>>) -> Vec<Box<dyn AgentTool>> {
+    let allowed = agent.tools.as_deref().unwrap_or_default();
+    for name in allowed {
+        if !tools.iter().any(|tool| tool.name() == name) {
+            // MCP tools are missing while their server is down, the chat goes on without them
+            warn!("Agent '{}' allows the unknown tool '{name}'", agent.name);
+        }
+    }
+    tools
+        .into_iter()
+        .filter(|tool| allowed.iter().any(|name| name == tool.name()))
+        .collect()
+}
+
+fn chat_loop_config(
+    session: &Session,
+    tools: Vec<Box<dyn AgentTool>>,
+    approval: ApprovalFn,
+    agent: Option<&AgentProfile>,
+) -> Result<AgentLoopConfig> {
+    let defaults = AgentLoopConfig::default();
+    let system_prompt = match agent {
+        Some(agent) => agent.system_prompt()?,
+        None => None,
+    };
+    let mut chat_options = defaults.chat_options.clone();
+    if let Some(temperature) = agent.and_then(|agent| agent.temperature) {
+        chat_options = chat_options.with_temperature(temperature);
+    }
+    // Exact counts for OpenAI models, the default heuristic for the other providers
+    let mut compaction = match BpeTokenCounter::for_model(&session.file.model) {
+        Ok(counter) => CompactionConfig::default().with_token_counter(counter),
+        Err(_) => CompactionConfig::default(),
+    };
+    if let Some(budget) = agent.and_then(|agent| agent.compaction_budget) {
+        compaction.token_budget = budget;
+    }
+
+    Ok(AgentLoopConfig {
+        model: session.file.model.clone(),
+        system_prompt: system_prompt
+            .unwrap_or_else(|| CHAT_SYSTEM_PROMPT.to_string())
+            .trim()
+            .to_string(),
+        tools,
+        max_turns: agent
+            .and_then(|agent| agent.max_turns)
+            .unwrap_or(defaults.max_turns),
+        chat_options,
+        approval: Some(approval),
+        // Large results are kept next to the session, the model pages through them
+        tool_output: ToolOutputConfig {
+            overflow: ToolOutputOverflow::Spill(ToolOutputStore::new(session.outputs_dir()?)),
+            ..Default::default()
+        },
+        skip_mutating_tools_on_steering: true,
+        compaction: Some(compaction),
+        ..defaults
+    })
+}
+
+/// Reads stdin on a dedicated thread so a pending read never keeps the runtime from shutting down.
+fn spawn_stdin_reader() -> UnboundedReceiver<String> {
+    let (tx, rx) = unbounded_channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            match line {
+                Ok(line) => {
+                    if tx.send(line).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    warn!("Failed to read from stdin: {e}");
+                    break;
+                }
+            }
+        }
+    });
+    rx
+}
+
+/// Asks the user on the terminal before a mutating tool runs.
+///
+/// The question itself is printed by `print_events` when the `ToolApprovalRequested` event
+/// arrives, this only waits for the answer. Each attempt sends a reply slot to `approvals`, the
+/// next line typed during the turn is routed to it (see `route_line`).
+fn approval_prompt(approvals: UnboundedSender<oneshot::Sender<String>>) -> ApprovalFn {
+    Box::new(move |_request| {
+        let approvals = approvals.clone();
+        Box::pin(async move {
+            loop {
+                let (answer_tx, answer_rx) = oneshot::channel();
+                let line = match approvals.send(answer_tx) {
+                    Ok(()) => answer_rx.await.ok(),
+                    Err(_) => None,
+                };
+                let Some(line) = line else {
+                    return ToolApproval::Deny {
+                        reason: String::from("no answer, the input was closed"),
+                    };
+                };
+                match parse_approval(&line) {
+                    Ok(approval) => return approval,
+                    Err(e) => eprint!("{e}, try again: "),
+                }
+            }
+        })
+    })
+}
+
+/// `y`/`yes` approves, `n`/`no` optionally followed by a reason denies, a JSON object replaces
+/// the arguments.
+fn parse_approval(line: &str) -> Result<ToolApproval> {
+    let line = line.trim();
+    if line.starts_with('{') {
+        let arguments: serde_json::Value =
+            serde_json::from_str(line).map_err(|e| anyhow!("Invalid JSON arguments ({e})"))?;
+        return Ok(ToolApproval::Edit { arguments });
+    }
+
+    let (answer, reason) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    match answer.to_lowercase().as_str() {
+        "y" | "yes" => Ok(ToolApproval::Approve),
+        "n" | "no" => Ok(ToolApproval::Deny {
+            reason: match reason.trim() {
+                "" => String::from("no reason given"),
+                reason => reason.to_string(),
+            },
+        }),
+        _ => Err(anyhow!(
+            "Answer y, n [reason] or replacement JSON arguments"
+        )),
+    }
+}
+
+/// One line summary of token usage, with its cost when it is known.
+pub(crate) fn usage_line(usage: &TokenUsage, cost: Option<f64>) -> String {
+    let mut line = format!(
+        "{} prompt tokens ({} cached), {} completion tokens",
+        usage.prompt_tokens, usage.cached_tokens, usage.completion_tokens
+    );
+    if let Some(cost) = cost {
+        line.push_str(&format!(", ${cost:.4}"));
+    }
+    line
+}
+
+/// Input of the REPL, read while a turn runs.
+struct TurnInput<'a> {
+    /// Lines typed by the user.
+    lines: &'a mut UnboundedReceiver<String>,
+    /// Reply slots of the approval questions waiting for an answer.
+    approvals: &'a mut UnboundedReceiver<oneshot::Sender<String>>,
+}
+
+/// Sends a line typed during a turn to the pending approval question, or to the agent as a
+/// steering message. The line is handed back when neither takes it.
+fn route_line(
+    line: String,
+    approvals: &mut UnboundedReceiver<oneshot::Sender<String>>,
+    steering: &UnboundedSender<ChatMessage>,
+) -> Option<String> {
+    let mut line = line;
+    while let Ok(answer) = approvals.try_recv() {
+        // A slot is left behind when its question was cancelled, try the next one
+        match answer.send(line) {
+            Ok(()) => return None,
+            Err(unanswered) => line = unanswered,
+        }
+    }
+    if line.trim().is_empty() {
+        return None;
+    }
+    match steering.send(ChatMessage::user(line.trim())) {
+        Ok(()) => None,
+        Err(_) => Some(line),
+    }
+}
+
+/// Runs one agent turn and returns the updated session, with a line typed during the turn that
+/// the agent did not take, to be used as the next prompt.
+async fn run_turn(
+    client: &dyn LlmBackend,
+    config: &AgentLoopConfig,
+    pricing: &HashMap<String, ModelPrice>,
+    show_reasoning: bool,
+    session: Session,
+    prompt: ChatMessage,
+    input: TurnInput<'_>,
+) -> Result<(Session, Option<String>)> {
+    let mut messages = session.file.messages.clone();
+    messages.push(prompt);
+
+    let (persist, shared) = session.persist_callback();
+    let (event_tx, event_rx) = unbounded_channel();
+    let event_rx = Session::track_usage(shared.clone(), event_rx);
+    let printer = tokio::spawn(print_events(event_rx, show_reasoning));
+
+    let cancel = CancellationToken::new();
+    let ctrl_c = tokio::spawn({
+        let cancel = cancel.clone();
+        async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                cancel.cancel();
+            }
+        }
+    });
+
+    let (steering_tx, steering_rx) = unbounded_channel();
+    let agent = agent_loop(
+        client,
+        config,
+        messages,
+        event_tx,
+        cancel,
+        Some(persist),
+        Some(steering_rx),
+    );
+    tokio::pin!(agent);
+    let mut leftover = None;
+    let result = loop {
+        tokio::select! {
+            result = &mut agent => break result,
+            Some(line) = input.lines.recv(), if leftover.is_none() => {
+                leftover = route_line(line, input.approvals, &steering_tx);
+            }
+        }
+    };
+    ctrl_c.abort();
+    let turn_usage = printer.await?;
+
+    if let Err(e) = result {
+        eprintln!("Turn ended early: {e}");
+    }
+
+    let session = shared.lock().map_err(|e| anyhow::anyhow!("{e}"))?.clone();
+    if !turn_usage.is_empty() {
+        let total: TokenUsage = turn_usage.values().copied().sum();
+        eprintln!("({})", usage_line(&total, usage_cost(&turn_usage, pricing)));
+    }
+    Ok((session, leftover))
+}
+
+/// Prints the events of a turn and returns the tokens it used, per model.
+///
+/// Reasoning goes to stderr, so the answers on stdout stay clean when they are piped.
+async fn print_events(
+    mut event_rx: UnboundedReceiver<AgentEvent>,
+    show_reasoning: bool,
+) -> BTreeMap<String, TokenUsage> {
+    let mut turn_usage: BTreeMap<String, TokenUsage> = BTreeMap::new();
+    let mut reasoning = false;
+    let mut preparing: HashSet<String> = HashSet::new();
+    while let Some(event) = event_rx.recv().await {
+        if reasoning && !matches!(event, AgentEvent::ReasoningDelta { .. }) {
+            eprintln!();
+            reasoning = false;
+        }
+        match event {
+            AgentEvent::ReasoningDelta { text } if show_reasoning => {
+                if !reasoning {
+                    eprint!("(thinking) ");
+                    reasoning = true;
+                }
+                eprint!("{text}");
+                let _ = std::io::stderr().flush();
+            }
+            AgentEvent::MessageDelta { text } => {
+                print!("{text}");
+                let _ = std::io::stdout().flush();
+            }
+            AgentEvent::MessageEnd { message } if message.content.contains_text() => {
+                println!();
+            }
+            AgentEvent::ToolCallDelta {
+                call_id, tool_name, ..
+            } if !tool_name.is_empty() && preparing.insert(call_id.clone()) => {
+                eprintln!("   preparing a {tool_name} call... (Ctrl-C cancels it)");
+            }
+            AgentEvent::ToolExecutionStart {
+                tool_name,
+                arguments,
+                ..
+            } => {
+                eprintln!("-> {tool_name} {arguments}");
+            }
+            AgentEvent::ToolExecutionUpdate { data, .. } if data.get("retry").is_some() => {
+                eprintln!(
+                    "   retry {} in {}ms: {}",
+                    data["retry"], data["delay_ms"], data["error"]
+                );
+            }
+            AgentEvent::ToolExecutionUpdate { data, .. } if data.get("subagent").is_some() => {
+                match data["event"].as_str() {
+                    Some("tool_start") => {
+                        eprintln!(
+                            "   {} -> {} {}",
+                            data["subagent"].as_str().unwrap_or_default(),
+                            data["tool_name"].as_str().unwrap_or_default(),
+                            data["arguments"]
+                        );
+                    }
+                    Some("tool_end") if data["is_error"] == true => {
+                        eprintln!(
+                            "   {} <- {} failed",
+                            data["subagent"].as_str().unwrap_or_default(),
+                            data["tool_name"].as_str().unwrap_or_default()
+                        );
+                    }
+                    _ => {}
+                }
+            }
+            AgentEvent::ToolApprovalRequested {
+                tool_name,
+                arguments,
+                ..
+            } => {
+                eprintln!("?? {tool_name} {arguments}");
+                eprint!("   Run it? y / n [reason] / replacement JSON arguments: ");
+                let _ = std::io::stderr().flush();
+            }
+            AgentEvent::ToolExecutionEnd {
+                tool_name,
+                is_error,
+                result,
+                ..
+            } => {
+                if is_error {
+                    eprintln!("<- {tool_name} failed: {result}");
+                } else {
+                    eprintln!("<- {tool_name} done");
+                }
+            }
+            AgentEvent::Usage { model, usage } => {
+                *turn_usage.entry(model).or_default() += usage;
+            }
+            AgentEvent::LlmRetry {
+                model,
+                retry,
+                delay,
+                error,
+            } => {
+                eprintln!("\n({model} failed, retry {retry} in {delay:?}: {error})");
+            }
+            AgentEvent::ModelFallback {
+                from_model,
+                to_model,
+                error,
+            } => {
+                eprintln!("\n({from_model} failed, switching to {to_model}: {error})");
+            }
+            AgentEvent::SteeringMessage { .. } => {
+                eprintln!("(your message was added to the conversation)");
+            }
+            AgentEvent::CompactionEnd {
+                original_count,
+                compacted_count,
+            } => {
+                eprintln!("(compacted {original_count} messages into {compacted_count})");
+            }
+            AgentEvent::Aborted { phase, .. } => {
+                eprintln!("(cancelled during {phase})");
+            }
+            _ => {}
+        }
+    }
+    turn_usage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn approval_answers_are_parsed() {
+        assert!(matches!(parse_approval("Y"), Ok(ToolApproval::Approve)));
+        assert!(matches!(
+            parse_approval("no  wrong item"),
+            Ok(ToolApproval::Deny { reason }) if reason == "wrong item"
+        ));
+        assert!(matches!(
+            parse_approval("n"),
+            Ok(ToolApproval::Deny { reason }) if reason == "no reason given"
+        ));
+        assert!(matches!(
+            parse_approval(r#"{"id": "abc"}"#),
+            Ok(ToolApproval::Edit { arguments }) if arguments["id"] == "abc"
+        ));
+        assert!(parse_approval("{not json").is_err());
+        assert!(parse_approval("maybe").is_err());
+    }
+
+    #[test]
+    fn agents_only_get_their_allowed_tools() {
+        let agent = AgentProfile {
+            name: String::from("planner"),
+            system_prompt: None,
+            system_prompt_file: None,
+            model: None,
+            temperature: None,
+            max_turns: None,
+            compaction_budget: None,
+            tools: Some(vec![String::from("things_to_do"), String::from("calendar")]),
+        };
+        let tools: Vec<Box<dyn AgentTool>> = vec![
+            Box::new(crate::tools::EngineeringFeedTool),
+            Box::new(crate::tools::ThingsToDoTool),
+        ];
+
+        let names: Vec<String> = allowed_tools(&agent, tools)
+            .iter()
+            .map(|tool| tool.name().to_string())
+            .collect();
+        assert_eq!(names, ["things_to_do"]);
+    }
+
+    #[test]
+    fn lines_answer_pending_approvals_before_steering() {
+        let (approval_tx, mut approvals) = unbounded_channel();
+        let (steering_tx, mut steering_rx) = unbounded_channel();
+
+        let (answer_tx, mut answer_rx) = oneshot::channel();
+        approval_tx
+            .send(answer_tx)
+            .expect("approvals receiver is alive");
+        assert_eq!(
+            route_line(String::from("y"), &mut approvals, &steering_tx),
+            None
+        );
+        assert_eq!(answer_rx.try_recv().ok().as_deref(), Some("y"));
+
+        assert_eq!(
+            route_line(String::from(" stop "), &mut approvals, &steering_tx),
+            None
+        );
+        let steered = steering_rx.try_recv().expect("line is sent as steering");
+        assert_eq!(steered.content.first_text(), Some("stop"));
+
+        drop(steering_rx);
+        assert_eq!(
+            route_line(String::from("next"), &mut approvals, &steering_tx).as_deref(),
+            Some("next")
+        );
+    }
+}
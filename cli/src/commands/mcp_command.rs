@@ -0,0 +1,39 @@
+use agent_core::{AgentLoopConfig, McpToolServer};
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use config::{Config, Profile};
+
+#[derive(Debug, Subcommand)]
+pub enum McpCommand {
+    #[clap(about = "Serve the daily bugle tools to MCP clients over stdio")]
+    Serve {
+        #[clap(
+            short,
+            long,
+            help = "Model used by the tools that make their own LLM requests (git messages)"
+        )]
+        model: Option<String>,
+    },
+}
+
+#[derive(Debug, Parser)]
+pub struct McpArgs {
+    #[clap(subcommand)]
+    pub command: McpCommand,
+}
+
+pub async fn handle_mcp_command(
+    args: McpArgs,
+    config: &Config,
+    profile: Option<&Profile>,
+) -> Result<()> {
+    match args.command {
+        McpCommand::Serve { model } => {
+            let model = model.unwrap_or_else(|| AgentLoopConfig::default().model);
+            let tools = crate::tools::mcp_server_tools(config, profile, &model);
+            McpToolServer::new("daily-bugle", env!("CARGO_PKG_VERSION"), tools)
+                .serve_stdio()
+                .await
+        }
+    }
+}
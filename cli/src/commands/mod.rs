@@ -1,3 +1,6 @@
 pub mod almanac_command;
+pub mod chat_command;
 pub mod fortress_command;
+pub mod mcp_command;
+pub mod session_command;
 pub mod tech_command;
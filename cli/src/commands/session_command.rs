@@ -0,0 +1,314 @@
+use super::chat_command::ChatSettings;
+use agent_core::{
+    Cassette, Session, SessionFile, SessionSummary, migrate_json_sessions, open_session_store,
+    usage_cost,
+};
+use anyhow::Result;
+use clap::{Parser, Subcommand, ValueEnum};
+use config::{Config, ModelPrice, Profile};
+use genai::chat::{ChatRole, ContentPart};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+const PREVIEW_LENGTH: usize = 60;
+const DEFAULT_SEARCH_LIMIT: usize = 20;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    Markdown,
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SessionCommand {
+    #[clap(about = "List saved sessions, most recently updated first")]
+    List,
+    #[clap(about = "Print the conversation of a session, including tool calls and results")]
+    Show {
+        #[clap(help = "ID of the session to show")]
+        id: String,
+    },
+    #[clap(about = "Continue a session in the interactive chat")]
+    Resume {
+        #[clap(help = "ID of the session to resume")]
+        id: String,
+        #[clap(flatten)]
+        settings: ChatSettings,
+    },
+    #[clap(about = "Start a new session from the messages of another one before an index")]
+    Fork {
+        #[clap(help = "ID of the session to fork")]
+        id: String,
+        #[clap(
+            long,
+            help = "Index of the first message to leave out, as numbered by `session show`"
+        )]
+        at: usize,
+        #[clap(short, long, help = "Continue the fork in the interactive chat")]
+        resume: bool,
+    },
+    #[clap(about = "List the forks of a session, including forks of forks")]
+    Descendants {
+        #[clap(help = "ID of the session whose forks to list")]
+        id: String,
+    },
+    #[clap(about = "Find the messages of past sessions containing every word of a query")]
+    Search {
+        #[clap(help = "Words to look for")]
+        query: String,
+        #[clap(
+            short,
+            long,
+            default_value_t = DEFAULT_SEARCH_LIMIT,
+            help = "Maximum number of messages to list"
+        )]
+        limit: usize,
+    },
+    #[clap(about = "Import the sessions saved as JSON files into the SQLite store")]
+    Migrate,
+    #[clap(
+        about = "Replay a chat recorded with `chat --record`, without calling the LLM or the tools"
+    )]
+    Replay {
+        #[clap(help = "ID of the recorded session")]
+        id: String,
+        #[clap(
+            long,
+            value_name = "PATH",
+            help = "Cassette file written by `--record`"
+        )]
+        cassette: PathBuf,
+        #[clap(
+            short,
+            long,
+            value_name = "NAME",
+            help = "Agent the chat was recorded with"
+        )]
+        agent: Option<String>,
+    },
+    #[clap(about = "Delete a session")]
+    Delete {
+        #[clap(help = "ID of the session to delete")]
+        id: String,
+    },
+    #[clap(about = "Export a session to stdout")]
+    Export {
+        #[clap(help = "ID of the session to export")]
+        id: String,
+        #[clap(short, long, value_enum, default_value = "markdown")]
+        format: ExportFormat,
+    },
+}
+
+#[derive(Debug, Parser)]
+pub struct SessionArgs {
+    #[clap(subcommand)]
+    pub command: SessionCommand,
+}
+
+pub async fn handle_session_command(
+    args: SessionArgs,
+    config: &Config,
+    profile: Option<&Profile>,
+) -> Result<()> {
+    let store = open_session_store(config.session_store)?;
+    match args.command {
+        SessionCommand::List => {
+            for summary in store.list()? {
+                println!("{}", summary_line(&summary));
+            }
+        }
+        SessionCommand::Show { id } => {
+            let session = Session::load(store.clone(), &id)?;
+            println!("{}", render_markdown(&session.file, &config.pricing));
+        }
+        SessionCommand::Resume { id, settings } => {
+            let session = Session::load(store.clone(), &id)?;
+            super::chat_command::run_chat(session, &settings, config, profile).await?;
+        }
+        SessionCommand::Fork { id, at, resume } => {
+            let fork = Session::load(store.clone(), &id)?.fork(at)?;
+            println!("Forked session {id} at message {at} into {}", fork.file.id);
+            if resume {
+                super::chat_command::run_chat(fork, &ChatSettings::default(), config, profile)
+                    .await?;
+            }
+        }
+        SessionCommand::Descendants { id } => {
+            for summary in Session::descendants(store.as_ref(), &id)? {
+                println!("{}", summary_line(&summary));
+            }
+        }
+        SessionCommand::Search { query, limit } => {
+            for hit in store.search(&query, limit)? {
+                println!(
+                    "{}  [{}]  {}",
+                    hit.session_id, hit.message_index, hit.snippet
+                );
+            }
+        }
+        SessionCommand::Migrate => {
+            let migrated = migrate_json_sessions()?;
+            println!("Imported {migrated} sessions");
+        }
+        SessionCommand::Replay {
+            id,
+            cassette,
+            agent,
+        } => {
+            let session = Session::load(store.clone(), &id)?;
+            let cassette = Cassette::load(&cassette)?;
+            let agent = agent
+                .as_deref()
+                .map(|name| config.agent(name))
+                .transpose()?;
+            super::chat_command::replay_chat(session, cassette, agent, config, profile).await?;
+        }
+        SessionCommand::Delete { id } => {
+            store.delete(&id)?;
+            println!("Deleted session {id}");
+        }
+        SessionCommand::Export { id, format } => {
+            let session = Session::load(store.clone(), &id)?;
+            match format {
+                ExportFormat::Markdown => {
+                    println!("{}", render_markdown(&session.file, &config.pricing))
+                }
+                ExportFormat::Json => println!("{}", serde_json::to_string_pretty(&session.file)?),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn preview(text: &str) -> String {
+    let text = text.trim();
+    if text.chars().count() > PREVIEW_LENGTH {
+        let truncated: String = text.chars().take(PREVIEW_LENGTH).collect();
+        format!("{truncated}...")
+    } else {
+        text.to_string()
+    }
+}
+
+fn summary_line(summary: &SessionSummary) -> String {
+    let fork = match (&summary.parent_id, summary.forked_at) {
+        (Some(parent), Some(at)) => format!("  fork of {parent} at {at}"),
+        (Some(parent), None) => format!("  fork of {parent}"),
+        _ => String::new(),
+    };
+    format!(
+        "{}  {}  created {}  updated {}  {} messages{}  {}",
+        summary.id,
+        summary.model,
+        summary.created_at.format(TIME_FORMAT),
+        summary.updated_at.format(TIME_FORMAT),
+        summary.message_count,
+        fork,
+        summary
+            .first_user_line
+            .as_deref()
+            .map(preview)
+            .unwrap_or_default(),
+    )
+}
+
+fn role_heading(role: &ChatRole) -> &'static str {
+    match role {
+        ChatRole::System => "System",
+        ChatRole::User => "User",
+        ChatRole::Assistant => "Assistant",
+        ChatRole::Tool => "Tool",
+    }
+}
+
+/// Renders the whole conversation as markdown, tool calls and results included.
+fn render_markdown(file: &SessionFile, pricing: &HashMap<String, ModelPrice>) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# Session {}\n", file.id);
+    let _ = writeln!(out, "- Model: {}", file.model);
+    let _ = writeln!(out, "- Created: {}", file.created_at.format(TIME_FORMAT));
+    if let Some(parent) = &file.parent_id {
+        let _ = writeln!(out, "- Forked from: {parent}");
+    }
+    let _ = writeln!(out, "- Updated: {}", file.updated_at.format(TIME_FORMAT));
+    if !file.usage.is_empty() {
+        let _ = writeln!(
+            out,
+            "- Usage: {}",
+            super::chat_command::usage_line(&file.total_usage(), usage_cost(&file.usage, pricing))
+        );
+    }
+    out.push('\n');
+
+    for (index, message) in file.messages.iter().enumerate() {
+        let _ = writeln!(out, "## [{index}] {}\n", role_heading(&message.role));
+        for part in message.content.parts() {
+            match part {
+                ContentPart::Text(text) => {
+                    let _ = writeln!(out, "{}\n", text.trim());
+                }
+                ContentPart::ToolCall(call) => {
+                    let arguments = serde_json::to_string_pretty(&call.fn_arguments)
+                        .unwrap_or_else(|_| call.fn_arguments.to_string());
+                    let _ = writeln!(
+                        out,
+                        "**Tool call** `{}` ({})\n\n```json\n{}\n```\n",
+                        call.fn_name, call.call_id, arguments
+                    );
+                }
+                ContentPart::ToolResponse(response) => {
+                    let _ = writeln!(
+                        out,
+                        "**Tool result** ({})\n\n```\n{}\n```\n",
+                        response.call_id,
+                        response.content.trim()
+                    );
+                }
+                ContentPart::Binary(binary) => {
+                    let _ = writeln!(
+                        out,
+                        "[{} attachment{}]\n",
+                        binary.content_type,
+                        binary
+                            .name
+                            .as_ref()
+                            .map(|n| format!(": {n}"))
+                            .unwrap_or_default()
+                    );
+                }
+                ContentPart::ThoughtSignature(_) => {}
+            }
+        }
+    }
+    out.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use agent_core::test_support::weather_conversation;
+
+    fn session_file() -> SessionFile {
+        agent_core::test_support::session_file("abc", weather_conversation())
+    }
+
+    #[test]
+    fn summary_uses_first_non_empty_user_line() {
+        let file = session_file();
+        assert_eq!(file.first_user_line(), Some("What's the weather like?"));
+        assert!(summary_line(&file.summary()).contains("5 messages"));
+    }
+
+    #[test]
+    fn markdown_includes_tool_calls_and_results() {
+        let markdown = render_markdown(&session_file(), &HashMap::new());
+        assert!(markdown.contains("## [0] User"));
+        assert!(markdown.contains("## [3] Assistant"));
+        assert!(markdown.contains("**Tool call** `weather_forecast` (call_1)"));
+        assert!(markdown.contains("**Tool result** (call_1)"));
+        assert!(markdown.contains("It is 72°F and sunny."));
+    }
+}
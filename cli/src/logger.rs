@@ -2,7 +2,8 @@ use env_logger::Env;
 use log::warn;
 
 pub fn init_logging() {
-    const MEMBERS: [&str; 6] = [
+    const MEMBERS: [&str; 7] = [
+        "agent_core",
         "cli",
         "config",
         "local_storage",
@@ -17,6 +17,12 @@ pub enum Command {
     Fortress(commands::fortress_command::FortressArgs),
     #[clap(about = "Commands related to almanac")]
     Almanac(commands::almanac_command::AlmanacArgs),
+    #[clap(about = "Chat with the daily bugle agent")]
+    Chat(commands::chat_command::ChatArgs),
+    #[clap(about = "Commands related to saved chat sessions")]
+    Session(commands::session_command::SessionArgs),
+    #[clap(about = "Commands related to the Model Context Protocol")]
+    Mcp(commands::mcp_command::McpArgs),
 }
 
 #[derive(Debug, clap::Parser)]
@@ -50,5 +56,14 @@ async fn main() -> anyhow::Result<()> {
         Command::Almanac(args) => {
             commands::almanac_command::handle_almanac_command(args, profile).await
         }
+        Command::Chat(args) => {
+            commands::chat_command::handle_chat_command(args, &config, profile).await
+        }
+        Command::Session(args) => {
+            commands::session_command::handle_session_command(args, &config, profile).await
+        }
+        Command::Mcp(args) => {
+            commands::mcp_command::handle_mcp_command(args, &config, profile).await
+        }
     }
 }
@@ -0,0 +1,141 @@
+use agent_core::{AgentEvent, AgentLoopConfig, SubAgentTool, TypedTool};
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_util::sync::CancellationToken;
+use web_scraper::ScrapedEngineeringItems;
+
+const DEFAULT_LIMIT: usize = 20;
+
+const DIGEST_SYSTEM_PROMPT: &str = "
+You research engineering blogs for another assistant.
+
+Read the feeds the task needs with the engineering_feed tool, then answer with a short digest:
+one line per relevant post with its title, source, date and url, grouped by theme when there
+are many. Only include what the task asks for, your answer is all the other assistant sees.
+";
+
+/// The feed to read.
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedSource {
+    Aws,
+    DeepLearning,
+    Figma,
+    Github,
+    GoogleDevelopers,
+    HackernewsNews,
+    HackernewsJobs,
+    ImpervaApplicationSecurity,
+    ImpervaAvailability,
+    ImpervaDataSecurity,
+    ImpervaDdos,
+    ImpervaPerformance,
+    Lucumr,
+    Mdn,
+    Medium,
+    Netflix,
+    Notion,
+    Nytimes,
+    Openai,
+    Square,
+    Stripe,
+    Uber,
+}
+
+async fn scrape_feed(source: FeedSource) -> Result<ScrapedEngineeringItems> {
+    use web_scraper::*;
+
+    match source {
+        FeedSource::Aws => aws::scrape_aws_engineering_sitemap().await,
+        FeedSource::DeepLearning => deep_learning::scrape_deep_learning_sitemap().await,
+        FeedSource::Figma => figma::scrape_figma_engineering_blog().await,
+        FeedSource::Github => github::scrape_github_blog_sitemap().await,
+        FeedSource::GoogleDevelopers => google::scrape_google_developer_blogs_sitemap().await,
+        FeedSource::HackernewsNews => hackernews::scrape_hackernews_news(None).await,
+        FeedSource::HackernewsJobs => hackernews::scrape_hackernews_jobs(None).await,
+        FeedSource::ImpervaApplicationSecurity => {
+            imperva::scrape_imperva_application_security_sitemap().await
+        }
+        FeedSource::ImpervaAvailability => imperva::scrape_imperva_availability_sitemap().await,
+        FeedSource::ImpervaDataSecurity => imperva::scrape_imperva_data_security_sitemap().await,
+        FeedSource::ImpervaDdos => imperva::scrape_imperva_ddos_sitemap().await,
+        FeedSource::ImpervaPerformance => imperva::scrape_imperva_performance_sitemap().await,
+        FeedSource::Lucumr => lucumr::scrape_lucumr_atom_feed().await,
+        FeedSource::Mdn => mdn::scrape_mdn_sitemap().await,
+        FeedSource::Medium => medium::scrape_medium_engineering_blog_sitemap().await,
+        FeedSource::Netflix => netflix::scrape_netflix_tech_blog_sitemap().await,
+        FeedSource::Notion => notion::scrape_notion_blog_sitemap().await,
+        FeedSource::Nytimes => nytimes::scrape_nytimes_open_blog_sitemap().await,
+        FeedSource::Openai => openai::scrape_openai_sitemap().await,
+        FeedSource::Square => square::scrape_square_engineering_blog_sitemap().await,
+        FeedSource::Stripe => stripe::scrape_stripe_engineering_blog_sitemap().await,
+        FeedSource::Uber => uber::scrape_uber_engineering_blog().await,
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct EngineeringFeedArgs {
+    source: FeedSource,
+    /// Maximum number of items to return (default: 20)
+    #[schemars(range(min = 1))]
+    limit: Option<usize>,
+}
+
+/// Most recent posts of one of the scraped engineering blogs and sitemaps.
+pub struct EngineeringFeedTool;
+
+#[async_trait::async_trait]
+impl TypedTool for EngineeringFeedTool {
+    type Args = EngineeringFeedArgs;
+
+    fn name(&self) -> &str {
+        "engineering_feed"
+    }
+
+    fn description(&self) -> &str {
+        "List the most recent posts of an engineering blog, newsletter or documentation sitemap. \
+         Items are sorted newest first and contain the title, url and, when available, a \
+         summary and publication dates."
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    async fn run(
+        &self,
+        _call_id: &str,
+        args: EngineeringFeedArgs,
+        _event_tx: &UnboundedSender<AgentEvent>,
+        _cancel: &CancellationToken,
+    ) -> anyhow::Result<String> {
+        let mut items = scrape_feed(args.source)
+            .await
+            .map_err(super::transient_if_retryable)?;
+        items.sort_by_key(|item| std::cmp::Reverse(item.published.or(item.updated)));
+        items.truncate(args.limit.unwrap_or(DEFAULT_LIMIT));
+        Ok(serde_json::to_string(&items)?)
+    }
+}
+
+/// Sub-agent reading the engineering feeds, so the raw feed items stay out of the chat context.
+///
+/// It runs no hooks, like the chat loop. Hooks added to the chat loop must be given to it too,
+/// with [`SubAgentTool::with_hooks`].
+pub fn engineering_digest_tool(model: &str) -> SubAgentTool {
+    SubAgentTool::new(
+        "engineering_digest",
+        "Delegate research across the engineering feeds (e.g. \"summarize this week's posts of \
+         all engineering blogs\") to a sub-agent that reads them and returns a short digest. \
+         Prefer it over engineering_feed when several feeds are needed.",
+        AgentLoopConfig {
+            model: model.to_string(),
+            system_prompt: DIGEST_SYSTEM_PROMPT.trim().to_string(),
+            tools: vec![Box::new(EngineeringFeedTool)],
+            max_turns: 8,
+            ..Default::default()
+        },
+    )
+}
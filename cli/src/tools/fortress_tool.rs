@@ -0,0 +1,118 @@
+use super::NoArgs;
+use agent_core::{AgentEvent, TypedTool};
+use fortress::bitwarden::{CoreCommands, folder::Folder, item::Item};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_util::sync::CancellationToken;
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ListItemsArgs {
+    /// Folder whose items are listed (see bitwarden_list_folders). Without it, the items in no
+    /// folder are listed.
+    folder_id: Option<String>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct DeleteItemArgs {
+    /// ID of the item to delete (see bitwarden_list_items)
+    id: String,
+}
+
+/// Bitwarden vault item summaries. Secrets (passwords, totp, cards) are never part of a summary.
+pub struct BitwardenListItemsTool;
+
+#[async_trait::async_trait]
+impl TypedTool for BitwardenListItemsTool {
+    type Args = ListItemsArgs;
+
+    fn name(&self) -> &str {
+        "bitwarden_list_items"
+    }
+
+    fn description(&self) -> &str {
+        "List the items of a folder of the user's Bitwarden vault, or the items in no folder \
+         when none is given (id, name, notes, type, folder and revision date). Passwords and \
+         other secrets are not included."
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    async fn run(
+        &self,
+        _call_id: &str,
+        args: ListItemsArgs,
+        _event_tx: &UnboundedSender<AgentEvent>,
+        _cancel: &CancellationToken,
+    ) -> anyhow::Result<String> {
+        let mut item = Item::new(String::new(), String::new());
+        if let Some(id) = args.folder_id {
+            item = item.set_folder_id(id);
+        }
+        let items = item.list().await?;
+        Ok(serde_json::to_string(&items)?)
+    }
+}
+
+/// Bitwarden vault folders.
+pub struct BitwardenListFoldersTool;
+
+#[async_trait::async_trait]
+impl TypedTool for BitwardenListFoldersTool {
+    type Args = NoArgs;
+
+    fn name(&self) -> &str {
+        "bitwarden_list_folders"
+    }
+
+    fn description(&self) -> &str {
+        "List the folders (id and name) of the user's Bitwarden vault."
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    async fn run(
+        &self,
+        _call_id: &str,
+        _args: NoArgs,
+        _event_tx: &UnboundedSender<AgentEvent>,
+        _cancel: &CancellationToken,
+    ) -> anyhow::Result<String> {
+        let folders = Folder::new(String::new()).list().await?;
+        Ok(serde_json::to_string(&folders)?)
+    }
+}
+
+/// Moves a Bitwarden vault item to the trash.
+pub struct BitwardenDeleteItemTool;
+
+#[async_trait::async_trait]
+impl TypedTool for BitwardenDeleteItemTool {
+    type Args = DeleteItemArgs;
+
+    fn name(&self) -> &str {
+        "bitwarden_delete_item"
+    }
+
+    fn description(&self) -> &str {
+        "Delete an item of the user's Bitwarden vault. The item goes to the trash and can be \
+         restored from there."
+    }
+
+    async fn run(
+        &self,
+        _call_id: &str,
+        args: DeleteItemArgs,
+        _event_tx: &UnboundedSender<AgentEvent>,
+        _cancel: &CancellationToken,
+    ) -> anyhow::Result<String> {
+        let item = Item::get(args.id.clone())?;
+        item.delete().await?;
+        Ok(serde_json::to_string(&json!({ "deleted": args.id }))?)
+    }
+}
@@ -0,0 +1,86 @@
+use super::NoArgs;
+use agent_core::{AgentEvent, TypedTool};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_util::sync::CancellationToken;
+
+/// Conventional commit message for the staged changes of the current repository.
+pub struct GitCommitMessageTool {
+    model: String,
+}
+
+impl GitCommitMessageTool {
+    pub fn new(model: &str) -> Self {
+        Self {
+            model: model.to_string(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl TypedTool for GitCommitMessageTool {
+    type Args = NoArgs;
+
+    fn name(&self) -> &str {
+        "git_commit_message"
+    }
+
+    fn description(&self) -> &str {
+        "Generate a Conventional Commits message from the staged diff of the git repository in \
+         the current working directory. Does not create the commit."
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    async fn run(
+        &self,
+        _call_id: &str,
+        _args: NoArgs,
+        _event_tx: &UnboundedSender<AgentEvent>,
+        _cancel: &CancellationToken,
+    ) -> anyhow::Result<String> {
+        git::git_commit_message(&self.model).await
+    }
+}
+
+/// Pull request title and description for the commits ahead of `origin/main`.
+pub struct GitPullRequestMessageTool {
+    model: String,
+}
+
+impl GitPullRequestMessageTool {
+    pub fn new(model: &str) -> Self {
+        Self {
+            model: model.to_string(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl TypedTool for GitPullRequestMessageTool {
+    type Args = NoArgs;
+
+    fn name(&self) -> &str {
+        "git_pull_request_message"
+    }
+
+    fn description(&self) -> &str {
+        "Generate a pull request title and description from the commits of the current branch \
+         that are ahead of origin/main. Does not open the pull request."
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    async fn run(
+        &self,
+        _call_id: &str,
+        _args: NoArgs,
+        _event_tx: &UnboundedSender<AgentEvent>,
+        _cancel: &CancellationToken,
+    ) -> anyhow::Result<String> {
+        git::git_pull_request_message(&self.model).await
+    }
+}
@@ -0,0 +1,237 @@
+mod engineering_feed_tool;
+mod fortress_tool;
+mod git_tool;
+mod news_tool;
+mod spaced_recall_tool;
+mod things_to_do_tool;
+mod weather_tool;
+
+use agent_core::{AgentTool, McpConnection, TransientToolError};
+use config::{Config, Profile};
+use log::warn;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+pub use engineering_feed_tool::{EngineeringFeedTool, engineering_digest_tool};
+pub use fortress_tool::{
+    BitwardenDeleteItemTool, BitwardenListFoldersTool, BitwardenListItemsTool,
+};
+pub use git_tool::{GitCommitMessageTool, GitPullRequestMessageTool};
+pub use news_tool::TopHeadlinesTool;
+pub use spaced_recall_tool::{
+    SpacedRecallAddItemTool, SpacedRecallCategoriesTool, SpacedRecallDueItemsTool,
+    SpacedRecallReviewTool,
+};
+pub use things_to_do_tool::ThingsToDoTool;
+pub use weather_tool::WeatherForecastTool;
+
+/// Time an MCP server gets to start and list its tools.
+const MCP_STARTUP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+
+// Arguments of the tools that take none. Not documented with `///`, which would end up in
+// their schema as its description.
+#[derive(Deserialize, JsonSchema)]
+pub struct NoArgs {}
+
+/// Marks `error` as a [`TransientToolError`] when retrying may help: the connection failed or
+/// timed out, or the server answered with a 5xx, 408 or 429 status. Other errors, a bad request
+/// or a page that no longer parses, are returned unchanged.
+fn transient_if_retryable(error: anyhow::Error) -> anyhow::Error {
+    let retryable = error
+        .chain()
+        .filter_map(|e| e.downcast_ref::<reqwest::Error>())
+        .any(|e| {
+            e.is_connect()
+                || e.is_timeout()
+                || e.is_body()
+                || e.status().is_some_and(|status| {
+                    status.is_server_error()
+                        || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                        || status == reqwest::StatusCode::REQUEST_TIMEOUT
+                })
+        });
+    if retryable {
+        TransientToolError::new(error).into()
+    } else {
+        error
+    }
+}
+
+/// Every capability of the daily bugle, wrapped as a tool the agent can call.
+///
+/// `model` is used by the tools that make their own LLM requests (git messages, sub-agents).
+pub fn all_tools(
+    config: &Config,
+    profile: Option<&Profile>,
+    model: &str,
+) -> Vec<Box<dyn AgentTool>> {
+    vec![
+        Box::new(WeatherForecastTool::new(
+            profile.map(|p| (p.latitude, p.longitude)),
+        )),
+        Box::new(TopHeadlinesTool::new(config.news.clone())),
+        Box::new(EngineeringFeedTool),
+        Box::new(engineering_digest_tool(model)),
+        Box::new(ThingsToDoTool),
+        Box::new(GitCommitMessageTool::new(model)),
+        Box::new(GitPullRequestMessageTool::new(model)),
+        Box::new(BitwardenListItemsTool),
+        Box::new(BitwardenListFoldersTool),
+        Box::new(BitwardenDeleteItemTool),
+        Box::new(SpacedRecallCategoriesTool),
+        Box::new(SpacedRecallDueItemsTool),
+        Box::new(SpacedRecallAddItemTool),
+        Box::new(SpacedRecallReviewTool),
+    ]
+}
+
+/// The tools served by `mcp serve`: the feeds, spaced recall and git tools, without the vault
+/// and the sub-agents.
+pub fn mcp_server_tools(
+    config: &Config,
+    profile: Option<&Profile>,
+    model: &str,
+) -> Vec<Box<dyn AgentTool>> {
+    vec![
+        Box::new(WeatherForecastTool::new(
+            profile.map(|p| (p.latitude, p.longitude)),
+        )),
+        Box::new(TopHeadlinesTool::new(config.news.clone())),
+        Box::new(EngineeringFeedTool),
+        Box::new(ThingsToDoTool),
+        Box::new(SpacedRecallCategoriesTool),
+        Box::new(SpacedRecallDueItemsTool),
+        Box::new(SpacedRecallAddItemTool),
+        Box::new(SpacedRecallReviewTool),
+        Box::new(GitCommitMessageTool::new(model)),
+        Box::new(GitPullRequestMessageTool::new(model)),
+    ]
+}
+
+/// `tools` followed by the tools of the MCP servers declared in the config. A server that cannot
+/// be reached, or does not list its tools within [`MCP_STARTUP_TIMEOUT`], is skipped with a
+/// warning, so it does not keep the chat from starting.
+pub async fn with_mcp_tools(
+    config: &Config,
+    mut tools: Vec<Box<dyn AgentTool>>,
+) -> Vec<Box<dyn AgentTool>> {
+    for server in &config.mcp_server {
+        let connected = tokio::time::timeout(MCP_STARTUP_TIMEOUT, async {
+            McpConnection::connect(server).await?.tools().await
+        })
+        .await;
+        match connected {
+            Ok(Ok(server_tools)) => add_server_tools(&mut tools, &server.name, server_tools),
+            Ok(Err(e)) => warn!("Skipping MCP server '{}': {e}", server.name),
+            Err(_) => warn!(
+                "Skipping MCP server '{}': no answer within {} seconds",
+                server.name,
+                MCP_STARTUP_TIMEOUT.as_secs()
+            ),
+        }
+    }
+    tools
+}
+
+/// Adds the tools of an MCP server to `tools`, skipping with a warning those whose name is
+/// already taken, by a built-in tool or a tool of another server.
+fn add_server_tools(
+    tools: &mut Vec<Box<dyn AgentTool>>,
+    server: &str,
+    server_tools: Vec<Box<dyn AgentTool>>,
+) {
+    for tool in server_tools {
+        if tools.iter().any(|taken| taken.name() == tool.name()) {
+            warn!(
+                "Skipping tool '{}' of MCP server '{server}': the name is taken",
+                tool.name()
+            );
+        } else {
+            tools.push(tool);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::News;
+
+    fn test_config() -> Config {
+        Config {
+            news: News {
+                api_key: String::from("test"),
+                sources: None,
+            },
+            profile: vec![],
+            openai_api_key: None,
+            pricing: Default::default(),
+            mcp_server: vec![],
+            session_store: Default::default(),
+            agent: vec![],
+        }
+    }
+
+    #[test]
+    fn tool_definitions_match_their_names() {
+        let tools = all_tools(&test_config(), None, "gpt-4o");
+        let served = mcp_server_tools(&test_config(), None, "gpt-4o");
+        assert!(
+            served
+                .iter()
+                .all(|s| tools.iter().any(|t| t.name() == s.name())),
+            "served tools are agent tools"
+        );
+        let mut names = std::collections::HashSet::new();
+        for tool in &tools {
+            let definition = tool.definition();
+            assert_eq!(definition.name, tool.name());
+            if let Err(e) = agent_core::check_tool_schema(&definition) {
+                panic!("{e}");
+            }
+            assert!(
+                definition.description.is_some(),
+                "{} has no description",
+                tool.name()
+            );
+            let schema = definition
+                .schema
+                .unwrap_or_else(|| panic!("{} has no schema", tool.name()));
+            assert_eq!(schema["type"], "object", "{} schema", tool.name());
+            for required in schema["required"].as_array().into_iter().flatten() {
+                let required = required.as_str().expect("required entries are strings");
+                assert!(
+                    schema["properties"].get(required).is_some(),
+                    "{}: required property {required} is not declared",
+                    tool.name()
+                );
+            }
+            assert!(names.insert(tool.name().to_string()), "duplicate tool name");
+        }
+    }
+
+    #[test]
+    fn server_tools_do_not_shadow_taken_names() {
+        let mut tools: Vec<Box<dyn AgentTool>> = vec![Box::new(ThingsToDoTool)];
+        add_server_tools(
+            &mut tools,
+            "bugle",
+            vec![Box::new(ThingsToDoTool), Box::new(EngineeringFeedTool)],
+        );
+        let names: Vec<&str> = tools.iter().map(|tool| tool.name()).collect();
+        assert_eq!(names, ["things_to_do", "engineering_feed"]);
+    }
+
+    #[tokio::test]
+    async fn only_network_failures_are_transient() {
+        // Nothing listens on the discard port
+        let refused = reqwest::get("http://127.0.0.1:9")
+            .await
+            .expect_err("connection is refused");
+        let error = transient_if_retryable(anyhow::Error::new(refused).context("while scraping"));
+        assert!(error.is::<TransientToolError>());
+
+        let error = transient_if_retryable(anyhow::anyhow!("Unable to find content summary"));
+        assert!(!error.is::<TransientToolError>());
+    }
+}
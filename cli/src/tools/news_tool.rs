@@ -0,0 +1,112 @@
+use agent_core::{AgentEvent, TypedTool};
+use config::News;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use third_party_api::news::{
+    TopHeadlinesUrl,
+    request_response::{Category, Country},
+    top_headlines,
+};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_util::sync::CancellationToken;
+
+/// Country code the headlines are published in.
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HeadlineCountry {
+    Us,
+    Mx,
+}
+
+/// Headline category.
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HeadlineCategory {
+    Business,
+    Entertainment,
+    General,
+    Health,
+    Science,
+    Sports,
+    Technology,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct TopHeadlinesArgs {
+    country: Option<HeadlineCountry>,
+    category: Option<HeadlineCategory>,
+    /// Keywords or phrase to search the headlines for
+    query: Option<String>,
+    /// Number of headlines to return (default: 20)
+    #[schemars(range(min = 1, max = 100))]
+    page_size: Option<u32>,
+}
+
+/// Top headlines from newsapi.org, restricted to the configured sources unless a country or
+/// category is given.
+pub struct TopHeadlinesTool {
+    news: News,
+}
+
+impl TopHeadlinesTool {
+    pub fn new(news: News) -> Self {
+        Self { news }
+    }
+}
+
+#[async_trait::async_trait]
+impl TypedTool for TopHeadlinesTool {
+    type Args = TopHeadlinesArgs;
+
+    fn name(&self) -> &str {
+        "top_headlines"
+    }
+
+    fn description(&self) -> &str {
+        "Get the current top news headlines. Without a country or category the headlines come \
+         from the user's configured news sources."
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    async fn run(
+        &self,
+        _call_id: &str,
+        args: TopHeadlinesArgs,
+        _event_tx: &UnboundedSender<AgentEvent>,
+        _cancel: &CancellationToken,
+    ) -> anyhow::Result<String> {
+        let country = args.country.map(|country| match country {
+            HeadlineCountry::Us => Country::USA,
+            HeadlineCountry::Mx => Country::Mexico,
+        });
+        let category = args.category.map(|category| match category {
+            HeadlineCategory::Business => Category::Business,
+            HeadlineCategory::Entertainment => Category::Entertainment,
+            HeadlineCategory::General => Category::General,
+            HeadlineCategory::Health => Category::Health,
+            HeadlineCategory::Science => Category::Science,
+            HeadlineCategory::Sports => Category::Sports,
+            HeadlineCategory::Technology => Category::Technology,
+        });
+        // newsapi rejects requests that mix sources with a country or a category
+        let sources = if country.is_some() || category.is_some() {
+            None
+        } else {
+            self.news.sources.clone()
+        };
+        let headlines = top_headlines(TopHeadlinesUrl {
+            api_key: self.news.api_key.clone(),
+            country,
+            category,
+            sources,
+            query: args.query,
+            page_size: args.page_size,
+            page: None,
+        })
+        .await?;
+        Ok(serde_json::to_string(&headlines)?)
+    }
+}
@@ -0,0 +1,201 @@
+use super::NoArgs;
+use agent_core::{AgentEvent, TypedTool};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+use spaced_recall::{ItemInsert, Rating};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_util::sync::CancellationToken;
+
+const DEFAULT_DUE_LIMIT: u8 = 10;
+
+#[derive(Deserialize, JsonSchema)]
+pub struct DueItemsArgs {
+    /// Maximum number of items to return (default: 10)
+    limit: Option<u8>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct AddItemArgs {
+    /// ID of the category of the card (see spaced_recall_categories)
+    category_id: i64,
+    /// Question side of the card
+    front: String,
+    /// Answer side of the card
+    back: String,
+}
+
+/// How well the user recalled the answer.
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ReviewRating {
+    Again,
+    Hard,
+    Good,
+    Easy,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ReviewArgs {
+    /// ID of the reviewed card
+    item_id: i64,
+    rating: ReviewRating,
+}
+
+/// Categories of the spaced recall cards.
+pub struct SpacedRecallCategoriesTool;
+
+#[async_trait::async_trait]
+impl TypedTool for SpacedRecallCategoriesTool {
+    type Args = NoArgs;
+
+    fn name(&self) -> &str {
+        "spaced_recall_categories"
+    }
+
+    fn description(&self) -> &str {
+        "List the categories (id, name and description) of the user's spaced recall cards."
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    async fn run(
+        &self,
+        _call_id: &str,
+        _args: NoArgs,
+        _event_tx: &UnboundedSender<AgentEvent>,
+        _cancel: &CancellationToken,
+    ) -> anyhow::Result<String> {
+        let categories: Vec<serde_json::Value> =
+            spaced_recall::get_categories(spaced_recall::connection()?)?
+                .into_iter()
+                .map(|category| {
+                    json!({
+                        "id": category.id,
+                        "name": category.name,
+                        "description": category.description,
+                    })
+                })
+                .collect();
+        Ok(serde_json::to_string(&categories)?)
+    }
+}
+
+/// Spaced recall cards due for review, most overdue first.
+pub struct SpacedRecallDueItemsTool;
+
+#[async_trait::async_trait]
+impl TypedTool for SpacedRecallDueItemsTool {
+    type Args = DueItemsArgs;
+
+    fn name(&self) -> &str {
+        "spaced_recall_due_items"
+    }
+
+    fn description(&self) -> &str {
+        "List the spaced recall cards due for review, most overdue first. Quiz the user with the \
+         front of a card before revealing the back, then record how it went with \
+         spaced_recall_review."
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    async fn run(
+        &self,
+        _call_id: &str,
+        args: DueItemsArgs,
+        _event_tx: &UnboundedSender<AgentEvent>,
+        _cancel: &CancellationToken,
+    ) -> anyhow::Result<String> {
+        let limit = args.limit.unwrap_or(DEFAULT_DUE_LIMIT);
+        let items: Vec<serde_json::Value> =
+            spaced_recall::get_due_items(limit, spaced_recall::connection()?)?
+                .into_iter()
+                .map(|(item, state)| {
+                    json!({
+                        "id": item.id,
+                        "category_id": item.category_id,
+                        "front": item.front,
+                        "back": item.back,
+                        "due_at": state.due_at,
+                        "reps": state.reps,
+                        "lapses": state.lapses,
+                    })
+                })
+                .collect();
+        Ok(serde_json::to_string(&items)?)
+    }
+}
+
+/// Adds a spaced recall card, due for review right away.
+pub struct SpacedRecallAddItemTool;
+
+#[async_trait::async_trait]
+impl TypedTool for SpacedRecallAddItemTool {
+    type Args = AddItemArgs;
+
+    fn name(&self) -> &str {
+        "spaced_recall_add_item"
+    }
+
+    fn description(&self) -> &str {
+        "Add a card to the user's spaced recall deck. The card is due for review right away."
+    }
+
+    async fn run(
+        &self,
+        _call_id: &str,
+        args: AddItemArgs,
+        _event_tx: &UnboundedSender<AgentEvent>,
+        _cancel: &CancellationToken,
+    ) -> anyhow::Result<String> {
+        spaced_recall::create_item(
+            ItemInsert {
+                category_id: args.category_id,
+                front: args.front,
+                back: args.back,
+                created_at: None,
+            },
+            spaced_recall::connection()?,
+        )?;
+        Ok(serde_json::to_string(&json!({ "added": true }))?)
+    }
+}
+
+/// Records the review of a spaced recall card, which schedules its next review.
+pub struct SpacedRecallReviewTool;
+
+#[async_trait::async_trait]
+impl TypedTool for SpacedRecallReviewTool {
+    type Args = ReviewArgs;
+
+    fn name(&self) -> &str {
+        "spaced_recall_review"
+    }
+
+    fn description(&self) -> &str {
+        "Record how well the user recalled a spaced recall card: again (forgot it), hard, good \
+         or easy. The next review is scheduled from the rating."
+    }
+
+    async fn run(
+        &self,
+        _call_id: &str,
+        args: ReviewArgs,
+        _event_tx: &UnboundedSender<AgentEvent>,
+        _cancel: &CancellationToken,
+    ) -> anyhow::Result<String> {
+        let rating = match args.rating {
+            ReviewRating::Again => Rating::Again,
+            ReviewRating::Hard => Rating::Hard,
+            ReviewRating::Good => Rating::Good,
+            ReviewRating::Easy => Rating::Easy,
+        };
+        spaced_recall::update_item_state(args.item_id, rating, spaced_recall::connection()?)?;
+        Ok(serde_json::to_string(&json!({ "reviewed": args.item_id }))?)
+    }
+}
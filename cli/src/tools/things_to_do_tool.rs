@@ -0,0 +1,67 @@
+use agent_core::{AgentEvent, TypedTool};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_util::sync::CancellationToken;
+use web_scraper::time_out::{ThingsToDoCycle, scrape_things_to_do};
+
+/// The period the suggestions should cover.
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Cycle {
+    Today,
+    Week,
+    Weekend,
+    Month,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ThingsToDoArgs {
+    cycle: Cycle,
+}
+
+/// TimeOut New York "things to do" articles, scraped with a headless browser.
+pub struct ThingsToDoTool;
+
+#[async_trait::async_trait]
+impl TypedTool for ThingsToDoTool {
+    type Args = ThingsToDoArgs;
+
+    fn name(&self) -> &str {
+        "things_to_do"
+    }
+
+    fn description(&self) -> &str {
+        "Get TimeOut's list of things to do in New York City for today, this week, this weekend \
+         or this month. Scraping is slow the first time, results are cached."
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        // The first scrape starts a headless browser and renders the whole page
+        Some(Duration::from_secs(300))
+    }
+
+    async fn run(
+        &self,
+        _call_id: &str,
+        args: ThingsToDoArgs,
+        _event_tx: &UnboundedSender<AgentEvent>,
+        _cancel: &CancellationToken,
+    ) -> anyhow::Result<String> {
+        let cycle = match args.cycle {
+            Cycle::Today => ThingsToDoCycle::Today,
+            Cycle::Week => ThingsToDoCycle::Week,
+            Cycle::Weekend => ThingsToDoCycle::Weekend,
+            Cycle::Month => ThingsToDoCycle::Month,
+        };
+        let things_to_do = scrape_things_to_do(cycle)
+            .await
+            .map_err(super::transient_if_retryable)?;
+        Ok(serde_json::to_string(&things_to_do)?)
+    }
+}
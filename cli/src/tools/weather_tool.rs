@@ -0,0 +1,86 @@
+use agent_core::{AgentEvent, TypedTool};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use third_party_api::weather::{SupportedMode, WeatherForecastToolInputs, weather_forecast_tool};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_util::sync::CancellationToken;
+
+/// Granularity of the forecast.
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ForecastMode {
+    Current,
+    Hourly,
+    Daily,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct WeatherForecastArgs {
+    mode: ForecastMode,
+    /// Latitude of the location, defaults to the user's profile
+    latitude: Option<f64>,
+    /// Longitude of the location, defaults to the user's profile
+    longitude: Option<f64>,
+    /// Number of days to forecast (default: 1, or 7 for daily)
+    #[schemars(range(min = 1, max = 16))]
+    forecast_days: Option<u8>,
+}
+
+/// Weather forecast from open-meteo. Falls back to the selected profile's coordinates.
+pub struct WeatherForecastTool {
+    location: Option<(f64, f64)>,
+}
+
+impl WeatherForecastTool {
+    pub fn new(location: Option<(f64, f64)>) -> Self {
+        Self { location }
+    }
+}
+
+#[async_trait::async_trait]
+impl TypedTool for WeatherForecastTool {
+    type Args = WeatherForecastArgs;
+
+    fn name(&self) -> &str {
+        "weather_forecast"
+    }
+
+    fn description(&self) -> &str {
+        "Get the weather forecast in Fahrenheit. `current` returns the conditions right now, \
+         `hourly` returns one entry per hour and `daily` returns one entry per day with sunrise, \
+         sunset and min/max temperatures. Coordinates default to the user's profile location."
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    async fn run(
+        &self,
+        _call_id: &str,
+        args: WeatherForecastArgs,
+        _event_tx: &UnboundedSender<AgentEvent>,
+        _cancel: &CancellationToken,
+    ) -> anyhow::Result<String> {
+        let mode = match args.mode {
+            ForecastMode::Current => SupportedMode::Current,
+            ForecastMode::Hourly => SupportedMode::Hourly,
+            ForecastMode::Daily => SupportedMode::Daily,
+        };
+        let (latitude, longitude) = match (args.latitude, args.longitude, self.location) {
+            (Some(latitude), Some(longitude), _) => (latitude, longitude),
+            (_, _, Some(location)) => location,
+            _ => anyhow::bail!("No coordinates given and no profile location is configured"),
+        };
+
+        let forecast = weather_forecast_tool(WeatherForecastToolInputs {
+            latitude,
+            longitude,
+            forecast_days: args.forecast_days,
+            mode,
+        })
+        .await
+        .map_err(super::transient_if_retryable)?;
+        Ok(serde_json::to_string(&forecast)?)
+    }
+}
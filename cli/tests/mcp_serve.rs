@@ -0,0 +1,54 @@
+//! Runs `daily-bugle mcp serve` as a child process and talks to it over stdio, the way MCP
+//! clients do.
+
+use agent_core::McpConnection;
+
+#[tokio::test]
+async fn mcp_serve_answers_over_stdio() {
+    let config_dir = agent_core::test_support::TempDir::new("daily-bugle-mcp");
+    std::fs::write(
+        config_dir.path().join("config.toml"),
+        "profile = []\n\n[news]\napi_key = \"unused\"\n",
+    )
+    .expect("config is written");
+
+    let server = config::McpServer {
+        name: String::from("bugle"),
+        command: Some(String::from(env!("CARGO_BIN_EXE_daily-bugle"))),
+        args: vec![String::from("mcp"), String::from("serve")],
+        env: [(
+            String::from("DAILY_BUGLE_CONFIG"),
+            config_dir.path().display().to_string(),
+        )]
+        .into(),
+        url: None,
+    };
+    let error = async {
+        let connection = tokio::time::timeout(
+            std::time::Duration::from_secs(30),
+            McpConnection::connect(&server),
+        )
+        .await??;
+        let tools = connection.tools().await?;
+        let forecast = tools
+            .iter()
+            .find(|tool| tool.name() == "bugle_weather_forecast")
+            .ok_or_else(|| anyhow::anyhow!("the forecast tool is served"))?;
+
+        let (event_tx, _event_rx) = tokio::sync::mpsc::unbounded_channel();
+        let cancel = tokio_util::sync::CancellationToken::new();
+        let error = forecast
+            .execute(
+                "call_1",
+                serde_json::json!({ "mode": "yearly" }),
+                &event_tx,
+                &cancel,
+            )
+            .await
+            .expect_err("the server checks the arguments");
+        anyhow::Ok(error.to_string())
+    }
+    .await
+    .expect("the server is reachable over stdio");
+    assert!(error.contains("invalid_arguments"), "{error}");
+}
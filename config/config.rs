@@ -1,6 +1,7 @@
 use anyhow::{Context, bail};
 use log::info;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 const DAILY_BUGLE_CONFIG_VAR: &str = "DAILY_BUGLE_CONFIG";
@@ -22,11 +23,116 @@ pub struct Profile {
     pub google_calendar_credentials_file: Option<PathBuf>,
 }
 
+/// Price of a model in dollars per million tokens.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ModelPrice {
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+    /// Price of prompt tokens read from the provider's cache. Defaults to the input price.
+    pub cached_input_per_million: Option<f64>,
+}
+
+/// An MCP server whose tools are offered to the agent, declared as `[[mcp_server]]`. Set
+/// `command` to start it and talk to it over stdio, or `url` to reach it over streamable HTTP.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct McpServer {
+    /// Prefix of the server's tool names
+    pub name: String,
+    pub command: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Extra environment variables of the command
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    pub url: Option<String>,
+}
+
+/// Where chat sessions are saved, set with `session_store`. SQLite is the default, as listing
+/// and searching JSON sessions reads every one of them.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStoreKind {
+    /// An append-only JSON lines log per session
+    Json,
+    /// A SQLite database, with full-text search over the messages. The JSON sessions are
+    /// imported when the database is created, later ones with `session migrate`.
+    #[default]
+    Sqlite,
+}
+
+/// A named agent, declared as `[[agent]]` and picked with `chat --agent <name>`. Fields left
+/// out keep the defaults of the chat.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AgentProfile {
+    pub name: String,
+    pub system_prompt: Option<String>,
+    /// File holding the system prompt, instead of `system_prompt`. Relative paths start from
+    /// the config directory.
+    pub system_prompt_file: Option<PathBuf>,
+    pub model: Option<String>,
+    pub temperature: Option<f64>,
+    pub max_turns: Option<usize>,
+    /// Estimated tokens past which older messages are summarized
+    pub compaction_budget: Option<usize>,
+    /// Names of the tools the agent can call, every tool when left out
+    pub tools: Option<Vec<String>>,
+}
+
+impl AgentProfile {
+    /// The system prompt, read from `system_prompt_file` when it is set.
+    pub fn system_prompt(&self) -> anyhow::Result<Option<String>> {
+        match (&self.system_prompt, &self.system_prompt_file) {
+            (Some(_), Some(_)) => bail!(
+                "Agent '{}' sets both system_prompt and system_prompt_file",
+                self.name
+            ),
+            (Some(prompt), None) => Ok(Some(prompt.clone())),
+            (None, Some(file)) => {
+                let path = config_location()?.join(file);
+                let prompt = std::fs::read_to_string(&path).with_context(|| {
+                    format!(
+                        "Failed to read the system prompt of agent '{}' from {}",
+                        self.name,
+                        path.display()
+                    )
+                })?;
+                Ok(Some(prompt))
+            }
+            (None, None) => Ok(None),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     pub news: News,
     pub profile: Vec<Profile>,
     pub openai_api_key: Option<String>,
+    /// Prices keyed by model name, used to turn token usage into cost.
+    #[serde(default)]
+    pub pricing: HashMap<String, ModelPrice>,
+    #[serde(default)]
+    pub mcp_server: Vec<McpServer>,
+    #[serde(default)]
+    pub session_store: SessionStoreKind,
+    #[serde(default)]
+    pub agent: Vec<AgentProfile>,
+}
+
+impl Config {
+    /// The `[[agent]]` called `name`.
+    pub fn agent(&self, name: &str) -> anyhow::Result<&AgentProfile> {
+        self.agent
+            .iter()
+            .find(|agent| agent.name == name)
+            .with_context(|| {
+                let names: Vec<&str> = self.agent.iter().map(|a| a.name.as_str()).collect();
+                match names.is_empty() {
+                    true => format!("No agent '{name}', none is declared in the config file"),
+                    false => format!("No agent '{name}', declared: {}", names.join(", ")),
+                }
+            })
+    }
 }
 
 fn config_location() -> anyhow::Result<PathBuf> {
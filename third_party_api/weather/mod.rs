@@ -26,7 +26,7 @@ pub struct WeatherForecastToolInputs {
     pub mode: SupportedMode,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, serde::Serialize)]
 pub struct WeatherForecastEntry {
     pub weather: String,
     pub sunrise: Option<String>,
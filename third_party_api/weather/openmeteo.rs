@@ -330,15 +330,9 @@ impl WeatherForecastBuilder {
 
         let response = reqwest::get(url.clone())
             .await
-            .with_context(|| "Open-Meteo request failed")?;
-
-        if !response.status().is_success() {
-            bail!(
-                "Open-Meteo returned status {} for {}",
-                response.status(),
-                url
-            );
-        }
+            .with_context(|| "Open-Meteo request failed")?
+            .error_for_status()
+            .with_context(|| "Open-Meteo returned an error status")?;
 
         let data: WeatherApiResponse = response
             .json()
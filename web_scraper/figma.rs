@@ -3,50 +3,61 @@ use crate::{
     constant::{FIGMA_ENGINEERING_BLOG_STORAGE_CONSTANT, FIGMA_ENGINEERING_BLOG_URL},
     xml::request_url_document_text,
 };
-use anyhow::Result;
+use anyhow::{Context, Result};
 use local_storage::key::StorageKey;
 use log::{trace, warn};
 use scraper::Selector;
 
+fn parse_figma_engineering_blog(html: &str) -> Result<ScrapedEngineeringItems> {
+    let html = scraper::Html::parse_document(html);
+    let engineering_blogs_selector =
+        Selector::parse("section#more-engineering-blogs > div > ul > li > article").unwrap();
+    let mut entries: ScrapedEngineeringItems = Vec::new();
+    for element in html.select(&engineering_blogs_selector) {
+        trace!("Found engineering blog entry: {:?}", element);
+        let content_selector = Selector::parse("div > div > a.fig-bqm9r8").unwrap();
+        match element.select(&content_selector).last() {
+            Some(content) => {
+                let url = content
+                    .attr("href")
+                    .context("Engineering blog entry has no link")?
+                    .to_string();
+                let title_selector = Selector::parse("h3").unwrap();
+                let title = content
+                    .select(&title_selector)
+                    .last()
+                    .context("Engineering blog entry has no title")?
+                    .inner_html();
+                let summary_selector = Selector::parse("footer p").unwrap();
+                let summary = content
+                    .select(&summary_selector)
+                    .last()
+                    .context("Engineering blog entry has no summary")?
+                    .inner_html();
+                entries.push(ScrapedEngineeringItem {
+                    title,
+                    url,
+                    summary: Some(summary),
+                    ..Default::default()
+                });
+            }
+            None => {
+                warn!(
+                    "Could not find engineering blog entry content. Possibly a different blog format."
+                );
+                continue;
+            }
+        };
+    }
+    Ok(entries)
+}
+
 pub async fn scrape_figma_engineering_blog() -> Result<ScrapedEngineeringItems> {
     match local_storage::find_stored_item(FIGMA_ENGINEERING_BLOG_STORAGE_CONSTANT).await {
         Some(item) => Ok(item),
         None => {
             let res = request_url_document_text(FIGMA_ENGINEERING_BLOG_URL, None).await?;
-            let html = scraper::Html::parse_document(&res);
-            let engineering_blogs_selector =
-                Selector::parse("section#more-engineering-blogs > div > ul > li > article")
-                    .unwrap();
-            let mut entries: ScrapedEngineeringItems = Vec::new();
-            for element in html.select(&engineering_blogs_selector) {
-                trace!("Found engineering blog entry: {:?}", element);
-                let content_selector = Selector::parse("div > div > a.fig-bqm9r8").unwrap();
-                match element.select(&content_selector).last() {
-                    Some(content) => {
-                        let url = content.attr("href").unwrap().to_string();
-                        let title_selector = Selector::parse("h3").unwrap();
-                        let title = content.select(&title_selector).last().unwrap().inner_html();
-                        let summary_selector = Selector::parse("footer p").unwrap();
-                        let summary = content
-                            .select(&summary_selector)
-                            .last()
-                            .unwrap()
-                            .inner_html();
-                        entries.push(ScrapedEngineeringItem {
-                            title,
-                            url,
-                            summary: Some(summary),
-                            ..Default::default()
-                        });
-                    }
-                    None => {
-                        warn!(
-                            "Could not find engineering blog entry content. Possibly a different blog format."
-                        );
-                        continue;
-                    }
-                };
-            }
+            let entries = parse_figma_engineering_blog(&res)?;
             let storage_key =
                 StorageKey::new(FIGMA_ENGINEERING_BLOG_STORAGE_CONSTANT, None, Some(14 * 24));
             local_storage::write_item_to_storage(storage_key, &entries).await;
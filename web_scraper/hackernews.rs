@@ -6,7 +6,7 @@ use crate::{
     },
     xml::request_url_document_text,
 };
-use anyhow::Result;
+use anyhow::{Context, Result};
 use local_storage::key::StorageKey;
 use scraper::Selector;
 
@@ -27,6 +27,26 @@ fn create_url(url: &str, page: Page) -> String {
     url
 }
 
+fn parse_hackernews_page(html: &str) -> Result<ScrapedEngineeringItems> {
+    let html = scraper::Html::parse_document(html);
+    let title_selector =
+        Selector::parse("tr.athing.submission > td.title > span.titleline > a").unwrap();
+    let mut entries: ScrapedEngineeringItems = Vec::new();
+    for element in html.select(&title_selector) {
+        let url = element
+            .attr("href")
+            .context("Hacker News entry has no link")?
+            .to_string();
+        let title = element.inner_html();
+        entries.push(ScrapedEngineeringItem {
+            title,
+            url,
+            ..Default::default()
+        });
+    }
+    Ok(entries)
+}
+
 pub async fn scrape_hackernews_news(page: Option<Page>) -> Result<ScrapedEngineeringItems> {
     match local_storage::find_stored_item(HACKER_NEWS_NEWS_STORAGE_CONSTANT).await {
         Some(item) => Ok(item),
@@ -36,19 +56,7 @@ pub async fn scrape_hackernews_news(page: Option<Page>) -> Result<ScrapedEnginee
                 None,
             )
             .await?;
-            let html = scraper::Html::parse_document(&res);
-            let title_selector =
-                Selector::parse("tr.athing.submission > td.title > span.titleline > a").unwrap();
-            let mut entries: ScrapedEngineeringItems = Vec::new();
-            for element in html.select(&title_selector) {
-                let url = element.attr("href").unwrap().to_string();
-                let title = element.inner_html();
-                entries.push(ScrapedEngineeringItem {
-                    title,
-                    url,
-                    ..Default::default()
-                });
-            }
+            let entries = parse_hackernews_page(&res)?;
             let storage_key =
                 StorageKey::new(HACKER_NEWS_NEWS_STORAGE_CONSTANT, None, Some(1 * 24));
             local_storage::write_item_to_storage(storage_key, &entries).await;
@@ -66,19 +74,7 @@ pub async fn scrape_hackernews_jobs(page: Option<Page>) -> Result<ScrapedEnginee
                 None,
             )
             .await?;
-            let html = scraper::Html::parse_document(&res);
-            let title_selector =
-                Selector::parse("tr.athing.submission > td.title > span.titleline > a").unwrap();
-            let mut entries: ScrapedEngineeringItems = Vec::new();
-            for element in html.select(&title_selector) {
-                let url = element.attr("href").unwrap().to_string();
-                let title = element.inner_html();
-                entries.push(ScrapedEngineeringItem {
-                    title,
-                    url,
-                    ..Default::default()
-                });
-            }
+            let entries = parse_hackernews_page(&res)?;
             let storage_key =
                 StorageKey::new(HACKER_NEWS_JOBS_STORAGE_CONSTANT, None, Some(1 * 24));
             local_storage::write_item_to_storage(storage_key, &entries).await;
@@ -178,27 +178,23 @@ pub async fn request_url_document_text(
     let client = builder
         .build()
         .with_context(|| "Unable to create request client")?;
-    let res = client.get(url).send().await?;
-    if res.status() != reqwest::StatusCode::OK {
-        bail!("Failed request to {} - {}", url, res.status());
-    } else {
-        if let Some(encoding) = accepted_encoding {
-            match encoding.to_str().unwrap_or_default() {
-                "gzip, deflate" => {
-                    let bytes = res
-                        .bytes()
-                        .await
-                        .with_context(|| "Failed to decode response body")?;
-                    let mut decoder = flate2::read::GzDecoder::new(&bytes[..]);
-                    let mut xml = String::new();
-                    decoder.read_to_string(&mut xml)?;
-                    Ok(xml)
-                }
-                _ => bail!("Unsupported encoding: {:?}", encoding),
+    let res = client.get(url).send().await?.error_for_status()?;
+    if let Some(encoding) = accepted_encoding {
+        match encoding.to_str().unwrap_or_default() {
+            "gzip, deflate" => {
+                let bytes = res
+                    .bytes()
+                    .await
+                    .with_context(|| "Failed to decode response body")?;
+                let mut decoder = flate2::read::GzDecoder::new(&bytes[..]);
+                let mut xml = String::new();
+                decoder.read_to_string(&mut xml)?;
+                Ok(xml)
             }
-        } else {
-            let xml = res.text().await?;
-            Ok(xml)
+            _ => bail!("Unsupported encoding: {:?}", encoding),
         }
+    } else {
+        let xml = res.text().await?;
+        Ok(xml)
     }
 }